@@ -0,0 +1,88 @@
+use std::str::{ self, FromStr };
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use newhope::Error;
+
+
+/// How keys and messages are written on disk and on stdio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Raw,
+    Hex,
+    Base64
+}
+
+impl Encoding {
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        match *self {
+            Encoding::Raw => data.to_vec(),
+            Encoding::Hex => {
+                let mut out = data.iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>();
+                out.push('\n');
+                out.into_bytes()
+            },
+            Encoding::Base64 => {
+                let mut out = BASE64.encode(data);
+                out.push('\n');
+                out.into_bytes()
+            }
+        }
+    }
+
+    /// Decode `input`, ignoring whitespace in the text encodings.
+    pub fn decode(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        if *self == Encoding::Raw {
+            return Ok(input.to_vec());
+        }
+
+        let text = str::from_utf8(input).map_err(|_| Error::InvalidEncoding)?;
+        let text = text.split_whitespace().collect::<String>();
+
+        match *self {
+            Encoding::Hex => {
+                if text.len() % 2 != 0 || !text.bytes().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(Error::InvalidEncoding);
+                }
+                (0..text.len()).step_by(2)
+                    .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| Error::InvalidEncoding))
+                    .collect()
+            },
+            Encoding::Base64 => BASE64.decode(&text).map_err(|_| Error::InvalidEncoding),
+            Encoding::Raw => unreachable!()
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Encoding, String> {
+        match s {
+            "raw" => Ok(Encoding::Raw),
+            "hex" => Ok(Encoding::Hex),
+            "base64" => Ok(Encoding::Base64),
+            _ => Err(format!("unknown encoding `{}`", s))
+        }
+    }
+}
+
+
+#[test]
+fn test_encoding() {
+    let data = (0..=255).collect::<Vec<u8>>();
+
+    for &encoding in &[Encoding::Raw, Encoding::Hex, Encoding::Base64] {
+        let encoded = encoding.encode(&data);
+        assert_eq!(encoding.decode(&encoded).unwrap(), data);
+    }
+
+    assert_eq!(Encoding::Hex.decode(b"00 ff\n10\r\n").unwrap(), [0x00, 0xff, 0x10]);
+    assert_eq!(Encoding::Base64.decode(b"AAEC\nAw==\n").unwrap(), [0, 1, 2, 3]);
+    assert_eq!(Encoding::Hex.decode(b"abc"), Err(Error::InvalidEncoding));
+    assert_eq!(Encoding::Hex.decode(b"zz"), Err(Error::InvalidEncoding));
+    assert_eq!(Encoding::Hex.decode(b"+0"), Err(Error::InvalidEncoding));
+    assert_eq!(Encoding::Base64.decode(b"AAE"), Err(Error::InvalidEncoding));
+    assert!("pem".parse::<Encoding>().is_err());
+}
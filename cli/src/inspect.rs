@@ -0,0 +1,117 @@
+use std::{ error, fmt };
+use std::io::{ self, Write };
+use newhope::{ N, Q, POLY_BYTES, SENDABYTES, SENDBBYTES, Error };
+use newhope::poly::poly_frombytes;
+use newhope::newhope::rec_frombytes;
+
+
+/// Summary of a polynomial's coefficients.
+pub struct Stats {
+    pub min: u16,
+    pub max: u16,
+    pub mean: f64,
+    pub stddev: f64,
+    /// Coefficients `>= Q`, which no honest encoder produces.
+    pub unreduced: usize
+}
+
+impl Stats {
+    pub fn new(coeffs: &[u16; N]) -> Stats {
+        let mean = coeffs.iter().map(|&x| x as f64).sum::<f64>() / N as f64;
+        let var = coeffs.iter()
+            .map(|&x| (x as f64 - mean) * (x as f64 - mean))
+            .sum::<f64>() / N as f64;
+
+        Stats {
+            min: *coeffs.iter().min().unwrap(),
+            max: *coeffs.iter().max().unwrap(),
+            mean,
+            stddev: var.sqrt(),
+            unreduced: coeffs.iter().filter(|&&x| x as usize >= Q).count()
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // a uniform coefficient mod Q
+        let mean = (Q - 1) as f64 / 2.;
+        let stddev = (((Q * Q - 1) as f64) / 12.).sqrt();
+
+        write!(
+            f,
+            "min {}, max {}, mean {:.1} (uniform {:.1}), stddev {:.1} (uniform {:.1}), {} >= q",
+            self.min, self.max, self.mean, mean, self.stddev, stddev, self.unreduced
+        )
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn poly<W: Write>(out: &mut W, name: &str, bytes: &[u8]) -> io::Result<()> {
+    let mut coeffs = [0; N];
+    poly_frombytes(bytes, &mut coeffs);
+    writeln!(out, "  {}: {}", name, Stats::new(&coeffs))
+}
+
+/// Describe an offer, reply or secret key, telling them apart by length.
+pub fn inspect<W: Write>(out: &mut W, bytes: &[u8]) -> Result<(), Box<dyn error::Error>> {
+    match bytes.len() {
+        SENDABYTES => {
+            let (b, seed) = bytes.split_at(POLY_BYTES);
+            writeln!(out, "offer ({} bytes)", bytes.len())?;
+            poly(out, "b", b)?;
+            writeln!(out, "  seed: {}", hex(seed))?;
+        },
+        SENDBBYTES => {
+            let (u, r) = bytes.split_at(POLY_BYTES);
+            let mut c = [0; N];
+            rec_frombytes(r, &mut c);
+            let mut counts = [0; 4];
+            for &x in c.iter() {
+                counts[x as usize] += 1;
+            }
+
+            writeln!(out, "reply ({} bytes)", bytes.len())?;
+            poly(out, "u", u)?;
+            writeln!(
+                out, "  rec: 0: {}, 1: {}, 2: {}, 3: {}",
+                counts[0], counts[1], counts[2], counts[3]
+            )?;
+        },
+        POLY_BYTES => {
+            writeln!(out, "secret key ({} bytes)", bytes.len())?;
+            poly(out, "s (ntt domain)", bytes)?;
+        },
+        _ => return Err(Error::InvalidLength.into())
+    }
+    Ok(())
+}
+
+
+#[test]
+fn test_inspect() {
+    use rand::{ Rng, OsRng, ChaChaRng };
+
+    let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+    let mut rng = OsRng::new().unwrap().gen::<ChaChaRng>();
+    ::newhope::keygen(&mut rng, &mut sk, &mut pk);
+
+    let mut out = Vec::new();
+    inspect(&mut out, &pk).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with("offer (1824 bytes)\n  b: "));
+    assert!(out.contains(", 0 >= q\n"));
+    assert!(out.contains(&format!("  seed: {}\n", hex(&pk[POLY_BYTES..]))));
+
+    let mut out = Vec::new();
+    inspect(&mut out, &[0xff; SENDBBYTES]).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("min 16383, max 16383"));
+    assert!(out.contains("1024 >= q"));
+    assert!(out.contains("rec: 0: 0, 1: 0, 2: 0, 3: 1024"));
+
+    assert!(inspect(&mut Vec::new(), &[0; 32]).is_err());
+}
@@ -0,0 +1,202 @@
+//! `newhope` command-line tool.
+
+extern crate rand;
+extern crate base64;
+extern crate newhope;
+
+mod encoding;
+mod inspect;
+
+use std::{ env, error, fs, process };
+use std::io::{ self, Read, Write };
+#[cfg(unix)] use std::os::unix::fs::{ OpenOptionsExt, PermissionsExt };
+use rand::{ Rng, OsRng, ChaChaRng };
+use newhope::{ POLY_BYTES, SENDABYTES, SENDBBYTES, PublicKey, Reply, Error };
+use newhope::poly::poly_check;
+use encoding::Encoding;
+
+
+const USAGE: &str = "\
+usage: newhope [-e raw|hex|base64] <command> <paths>
+
+commands:
+    keygen <secret> <offer>          generate a secret key and an offer
+    respond <offer> <reply> <key>    answer an offer and derive the shared key
+    finish <secret> <reply> <key>    derive the shared key from a reply
+    inspect <message>                describe an offer, reply or secret key
+
+A path of `-` is stdin or stdout. The encoding (default hex) applies to
+every key and message read or written.";
+
+type Result<T> = ::std::result::Result<T, Box<dyn error::Error>>;
+
+
+fn read(path: &str, encoding: Encoding) -> Result<Vec<u8>> {
+    let input = if path == "-" {
+        let mut input = Vec::new();
+        io::stdin().read_to_end(&mut input)?;
+        input
+    } else {
+        fs::read(path)?
+    };
+    Ok(encoding.decode(&input)?)
+}
+
+fn write(path: &str, encoding: Encoding, data: &[u8]) -> Result<()> {
+    let output = encoding.encode(data);
+    if path == "-" {
+        io::stdout().write_all(&output)?;
+    } else {
+        fs::write(path, output)?;
+    }
+    Ok(())
+}
+
+/// `write`, but on unix the file is only readable by its owner.
+fn write_secret(path: &str, encoding: Encoding, data: &[u8]) -> Result<()> {
+    if path == "-" {
+        return write(path, encoding, data);
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)] options.mode(0o600);
+    let mut file = options.open(path)?;
+    #[cfg(unix)] file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(&encoding.encode(data))?;
+    Ok(())
+}
+
+fn rng() -> Result<ChaChaRng> {
+    Ok(OsRng::new()?.gen())
+}
+
+fn secret(bytes: &[u8]) -> Result<[u8; POLY_BYTES]> {
+    if bytes.len() != POLY_BYTES {
+        return Err(Error::InvalidLength.into());
+    }
+    if !poly_check(bytes) {
+        return Err(Error::InvalidKey.into());
+    }
+
+    let mut sk = [0; POLY_BYTES];
+    sk.copy_from_slice(bytes);
+    Ok(sk)
+}
+
+fn offer(bytes: &[u8]) -> Result<PublicKey> {
+    if bytes.len() != SENDABYTES {
+        return Err(Error::InvalidLength.into());
+    }
+    PublicKey::from_bytes(bytes).ok_or_else(|| Error::InvalidKey.into())
+}
+
+fn reply(bytes: &[u8]) -> Result<Reply> {
+    if bytes.len() != SENDBBYTES {
+        return Err(Error::InvalidLength.into());
+    }
+    Reply::from_bytes(bytes).ok_or_else(|| Error::InvalidKey.into())
+}
+
+
+fn keygen(encoding: Encoding, sk_path: &str, offer_path: &str) -> Result<()> {
+    let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+    newhope::keygen(rng()?, &mut sk, &mut pk);
+
+    write_secret(sk_path, encoding, &sk)?;
+    write(offer_path, encoding, &pk)
+}
+
+fn respond(encoding: Encoding, offer_path: &str, reply_path: &str, key_path: &str) -> Result<()> {
+    let pk = offer(&read(offer_path, encoding)?)?;
+    let (mut key, mut msg) = ([0; 32], [0; SENDBBYTES]);
+    newhope::sharedb(rng()?, &mut key, &pk.0, &mut msg);
+
+    write(reply_path, encoding, &msg)?;
+    write_secret(key_path, encoding, &key)
+}
+
+fn finish(encoding: Encoding, sk_path: &str, reply_path: &str, key_path: &str) -> Result<()> {
+    let sk = secret(&read(sk_path, encoding)?)?;
+    let msg = reply(&read(reply_path, encoding)?)?;
+    let mut key = [0; 32];
+    newhope::shareda(&mut key, &sk, &msg.0);
+
+    write_secret(key_path, encoding, &key)
+}
+
+/// Run a command line without the program name; `Ok(false)` means bad usage.
+fn run(args: &[String]) -> Result<bool> {
+    let mut args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut encoding = Encoding::Hex;
+    if let ["-e", name, ..] | ["--encoding", name, ..] = args[..] {
+        encoding = name.parse()?;
+        args.drain(..2);
+    }
+
+    if args.iter().filter(|&&arg| arg == "-").count() > 1 {
+        return Err("at most one path may be `-`".into());
+    }
+
+    match args[..] {
+        ["keygen", sk, pk] => keygen(encoding, sk, pk)?,
+        ["respond", pk, msg, key] => respond(encoding, pk, msg, key)?,
+        ["finish", sk, msg, key] => finish(encoding, sk, msg, key)?,
+        ["inspect", msg] => inspect::inspect(&mut io::stdout(), &read(msg, encoding)?)?,
+        _ => return Ok(false)
+    }
+    Ok(true)
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    match run(&args) {
+        Ok(true) => (),
+        Ok(false) => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+        Err(err) => {
+            eprintln!("newhope: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+
+#[test]
+fn test_handshake() {
+    let dir = env::temp_dir().join(format!("newhope-cli-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+    let run = |args: &[&str]| run(&args.iter().map(|&s| s.to_owned()).collect::<Vec<_>>());
+
+    fs::write(path("sk"), b"").unwrap();
+    #[cfg(unix)] fs::set_permissions(path("sk"), fs::Permissions::from_mode(0o644)).unwrap();
+
+    for &encoding in &["raw", "hex", "base64"] {
+        assert!(run(&["-e", encoding, "keygen", &path("sk"), &path("offer")]).unwrap());
+        assert!(run(&["-e", encoding, "respond", &path("offer"), &path("reply"), &path("keyb")]).unwrap());
+        assert!(run(&["-e", encoding, "finish", &path("sk"), &path("reply"), &path("keya")]).unwrap());
+
+        let keya = fs::read(path("keya")).unwrap();
+        assert_eq!(keya, fs::read(path("keyb")).unwrap());
+        assert_eq!(encoding.parse::<Encoding>().unwrap().decode(&keya).unwrap().len(), 32);
+    }
+
+    // secrets are private, even when they overwrite a readable file
+    #[cfg(unix)] {
+        for name in &["sk", "keya", "keyb"] {
+            assert_eq!(fs::metadata(path(name)).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    // an offer is not a secret key
+    assert!(run(&["-e", "base64", "finish", &path("offer"), &path("reply"), &path("keya")]).is_err());
+    assert!(run(&["finish", "-", "-", &path("keya")]).is_err());
+    assert!(!run(&["respond", &path("offer")]).unwrap());
+    assert!(!run(&[]).unwrap());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
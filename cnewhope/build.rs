@@ -5,6 +5,11 @@ use cc::Build;
 
 
 fn main() {
+    // `capi` links the Rust C ABI from the newhope crate instead
+    if cfg!(feature = "capi") {
+        return;
+    }
+
     #[cfg(not(feature = "tor"))] let cnewhope_root = Path::new("newhope").join("ref");
     #[cfg(feature = "tor")] let cnewhope_root = Path::new("newhope").join("torref");
     let mut cfg = Build::new();
@@ -18,13 +23,15 @@ fn main() {
         "newhope.c",
         "reduce.c",
         "fips202.c",
-        "randombytes.c",
 
         #[cfg(feature = "tor")] "batcher.c",
     ] {
         cfg.file(cnewhope_root.join(src));
     }
 
+    // callback-driven replacement for the reference randombytes.c
+    cfg.file("randombytes.c");
+
     cfg.include(cnewhope_root)
         .opt_level(3)
         .debug(true)
@@ -0,0 +1,26 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate newhope;
+extern crate cnewhope;
+
+use newhope::internals::batcher84;
+
+const LEN: usize = 16 * 84;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() != 2 * LEN {
+        return;
+    }
+
+    let mut x = [0; LEN];
+    for (c, b) in x.iter_mut().zip(data.chunks(2)) {
+        *c = b[0] as u16 | (b[1] as u16) << 8;
+    }
+    let mut y = x;
+
+    for i in 0..16 {
+        unsafe { cnewhope::batcher84(x[i..].as_mut_ptr()) };
+        batcher84(&mut y[i..]);
+    }
+    assert_eq!(&x[..], &y[..]);
+});
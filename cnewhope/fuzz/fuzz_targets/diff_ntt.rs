@@ -0,0 +1,28 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate newhope;
+extern crate cnewhope;
+
+use newhope::N;
+use newhope::internals::{ poly_ntt, poly_invntt };
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() != 2 * N {
+        return;
+    }
+
+    // the transforms are only defined on 14-bit coefficients
+    let mut p = cnewhope::Poly::default();
+    for (c, b) in p.coeffs.iter_mut().zip(data.chunks(2)) {
+        *c = (b[0] as u16 | (b[1] as u16) << 8) & 0x3fff;
+    }
+    let mut r = p.coeffs;
+
+    unsafe { cnewhope::poly_ntt(&mut p) };
+    poly_ntt(&mut r);
+    assert_eq!(&p.coeffs[..], &r[..]);
+
+    unsafe { cnewhope::poly_invntt(&mut p) };
+    poly_invntt(&mut r);
+    assert_eq!(&p.coeffs[..], &r[..]);
+});
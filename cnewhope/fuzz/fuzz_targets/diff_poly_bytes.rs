@@ -0,0 +1,26 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate newhope;
+extern crate cnewhope;
+
+use newhope::{ N, POLY_BYTES };
+use newhope::internals::{ poly_frombytes, poly_tobytes };
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() == POLY_BYTES {
+        let mut p = cnewhope::Poly::default();
+        let mut r = [0; N];
+        unsafe { cnewhope::poly_frombytes(&mut p, data.as_ptr()) };
+        poly_frombytes(data, &mut r);
+        assert_eq!(&p.coeffs[..], &r[..]);
+    } else if data.len() == 2 * N {
+        let mut p = cnewhope::Poly::default();
+        for (c, b) in p.coeffs.iter_mut().zip(data.chunks(2)) {
+            *c = b[0] as u16 | (b[1] as u16) << 8;
+        }
+        let (mut a, mut b) = ([0; POLY_BYTES], [0; POLY_BYTES]);
+        unsafe { cnewhope::poly_tobytes(a.as_mut_ptr(), &p) };
+        poly_tobytes(&p.coeffs, &mut b);
+        assert_eq!(&a[..], &b[..]);
+    }
+});
@@ -0,0 +1,44 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate rand;
+extern crate newhope;
+extern crate cnewhope;
+
+#[allow(dead_code)]
+#[path = "../../tests/replay/mod.rs"]
+mod replay;
+
+use newhope::N;
+use newhope::internals::{ helprec, rec };
+use replay::{ ReplayRng, chacha20 };
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() != 2 * N + 33 {
+        return;
+    }
+
+    let (v_bytes, rest) = data.split_at(2 * N);
+    let (seed, nonce) = (&rest[..32], rest[32]);
+
+    // both sides only agree on 14-bit coefficients, see test_f_g
+    let mut v = cnewhope::Poly::default();
+    for (c, b) in v.coeffs.iter_mut().zip(v_bytes.chunks(2)) {
+        *c = (b[0] as u16 | (b[1] as u16) << 8) & 0x3fff;
+    }
+
+    // helprec draws its 32 random bytes from chacha20(seed, nonce)
+    let mut stream = Vec::new();
+    chacha20(&mut stream, 32, nonce, seed);
+    let mut rng = ReplayRng::new(stream);
+
+    let mut c = cnewhope::Poly::default();
+    let mut r = [0; N];
+    unsafe { cnewhope::helprec(&mut c, &v, seed.as_ptr(), nonce) };
+    helprec(&mut r, &v.coeffs, &mut rng);
+    assert_eq!(&c.coeffs[..], &r[..]);
+
+    let (mut key, mut key2) = ([0; 32], [0; 32]);
+    unsafe { cnewhope::rec(key.as_mut_ptr(), &v, &c) };
+    rec(&mut key2, &v.coeffs, &r);
+    assert_eq!(key, key2);
+});
@@ -0,0 +1,19 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate newhope;
+extern crate cnewhope;
+
+use newhope::{ N, SEEDBYTES };
+use newhope::internals::poly_uniform;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() != SEEDBYTES {
+        return;
+    }
+
+    let mut p = cnewhope::Poly::default();
+    let mut r = [0; N];
+    unsafe { cnewhope::poly_uniform(&mut p, data.as_ptr()) };
+    poly_uniform(&mut r, data);
+    assert_eq!(&p.coeffs[..], &r[..]);
+});
@@ -4,6 +4,11 @@
 #![feature(repr_align)]
 #![feature(attr_literals)]
 
+#[cfg(feature = "capi")] extern crate newhope;
+
+use std::{ panic, slice };
+use std::os::raw::{ c_void, c_ulonglong };
+
 pub const N: usize = 1024;
 pub const Q: usize = 12289;
 pub const POLY_BYTES: usize = 1792;
@@ -25,10 +30,54 @@ impl Default for Poly {
     }
 }
 
+pub type RandombytesCallback = unsafe extern "C" fn(ctx: *mut c_void, x: *mut u8, xlen: c_ulonglong);
+
 extern "C" {
     pub fn newhope_keygen(send: *mut u8, sk: *mut Poly);
     pub fn newhope_sharedb(sharedkey: *mut u8, send: *mut u8, received: *const u8);
     pub fn newhope_shareda(sharedkey: *mut u8, ska: *const Poly, received: *const u8);
+
+    pub fn poly_uniform(a: *mut Poly, seed: *const u8);
+    pub fn poly_ntt(r: *mut Poly);
+    pub fn poly_invntt(r: *mut Poly);
+    pub fn poly_frombytes(r: *mut Poly, a: *const u8);
+    pub fn poly_tobytes(r: *mut u8, p: *const Poly);
+    pub fn helprec(c: *mut Poly, v: *const Poly, seed: *const u8, nonce: u8);
+    pub fn rec(key: *mut u8, v: *const Poly, c: *const Poly);
+    #[cfg(feature = "tor")] pub fn batcher84(x: *mut u16);
+
+    /// Route `randombytes` on the calling thread through `f`; `None` restores `/dev/urandom`.
+    pub fn randombytes_set_callback(f: Option<RandombytesCallback>, ctx: *mut c_void);
+}
+
+
+/// Run `f` with the C `randombytes` on this thread reading from `source`.
+pub fn with_randombytes<S, F, T>(mut source: S, f: F) -> T
+    where
+        S: FnMut(&mut [u8]),
+        F: FnOnce() -> T
+{
+    unsafe extern "C" fn trampoline<S: FnMut(&mut [u8])>(ctx: *mut c_void, x: *mut u8, xlen: c_ulonglong) {
+        let source = &mut *(ctx as *mut S);
+        let buf = slice::from_raw_parts_mut(x, xlen as usize);
+
+        // unwinding across the C frames is undefined
+        if panic::catch_unwind(panic::AssertUnwindSafe(|| source(buf))).is_err() {
+            ::std::process::abort();
+        }
+    }
+
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            unsafe { randombytes_set_callback(None, ::std::ptr::null_mut()) };
+        }
+    }
+
+    unsafe { randombytes_set_callback(Some(trampoline::<S>), &mut source as *mut S as *mut c_void) };
+    let _reset = Reset;
+    f()
 }
 
 
@@ -49,3 +98,43 @@ fn test_newhope() {
     assert!(keya != [0; 32]);
     assert_eq!(keya, keyb);
 }
+
+#[cfg(not(feature = "capi"))]
+#[test]
+fn test_randombytes_replay() {
+    fn run(stream: &[u8]) -> ([u8; SENDABYTES], [u8; SENDBBYTES], [u8; 32]) {
+        let (mut senda, mut sendb) = ([0; SENDABYTES], [0; SENDBBYTES]);
+        let (mut keya, mut keyb) = ([0; 32], [0; 32]);
+        let mut ska = Poly::default();
+        let mut stream = stream;
+
+        with_randombytes(
+            |buf: &mut [u8]| {
+                let (head, rest) = stream.split_at(buf.len());
+                buf.copy_from_slice(head);
+                stream = rest;
+            },
+            || unsafe {
+                newhope_keygen(senda.as_mut_ptr(), &mut ska);
+                newhope_sharedb(keyb.as_mut_ptr(), sendb.as_mut_ptr(), senda.as_ptr());
+                newhope_shareda(keya.as_mut_ptr(), &ska, sendb.as_ptr());
+            }
+        );
+
+        assert_eq!(keya, keyb);
+        (senda, sendb, keya)
+    }
+
+    let stream = (0..96).map(|i| i as u8).collect::<Vec<u8>>();
+    let (senda, sendb, key) = run(&stream);
+    let (senda2, sendb2, key2) = run(&stream);
+
+    assert_eq!(&senda[..], &senda2[..]);
+    assert_eq!(&sendb[..], &sendb2[..]);
+    assert_eq!(key, key2);
+
+    let mut other = stream.clone();
+    other[95] ^= 1;
+    let (_, sendb3, _) = run(&other);
+    assert!(&sendb[..] != &sendb3[..]);
+}
@@ -0,0 +1,71 @@
+//! Test helpers for driving both implementations from the same random
+//! stream; the differential fuzz targets include this file too.
+
+use rand::{ Rng, SeedableRng, ChaChaRng };
+
+
+/// Replays a fixed byte stream, panicking once it runs dry.
+pub struct ReplayRng {
+    stream: Vec<u8>,
+    pos: usize
+}
+
+impl ReplayRng {
+    pub fn new(stream: Vec<u8>) -> ReplayRng {
+        ReplayRng { stream, pos: 0 }
+    }
+}
+
+impl Rng for ReplayRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0; 4];
+        self.fill_bytes(&mut buf);
+        buf[0] as u32 | (buf[1] as u32) << 8 | (buf[2] as u32) << 16 | (buf[3] as u32) << 24
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        dest.copy_from_slice(&self.stream[self.pos..self.pos + dest.len()]);
+        self.pos += dest.len();
+    }
+}
+
+/// `crypto_stream_chacha20(out, len, {nonce, 0, ..}, key)` from the reference.
+pub fn chacha20(out: &mut Vec<u8>, len: usize, nonce: u8, key: &[u8]) {
+    let mut words = [0; 8];
+    for (w, k) in words.iter_mut().zip(key[..32].chunks(4)) {
+        *w = k[0] as u32 | (k[1] as u32) << 8 | (k[2] as u32) << 16 | (k[3] as u32) << 24;
+    }
+
+    // the 64-bit nonce occupies the high half of rand's 128-bit counter
+    let mut chacha = ChaChaRng::from_seed(&words);
+    chacha.set_counter(0, nonce as u64);
+    for _ in 0..(len / 4) {
+        let w = chacha.next_u32();
+        out.extend_from_slice(&[w as u8, (w >> 8) as u8, (w >> 16) as u8, (w >> 24) as u8]);
+    }
+}
+
+/// Run `f` with the C `randombytes` reading `stream`.
+pub fn replay<F, T>(stream: &[u8], f: F) -> T where F: FnOnce() -> T {
+    let mut stream = stream;
+    ::cnewhope::with_randombytes(
+        |buf: &mut [u8]| {
+            let (head, rest) = stream.split_at(buf.len());
+            buf.copy_from_slice(head);
+            stream = rest;
+        },
+        f
+    )
+}
+
+
+#[test]
+fn test_chacha20() {
+    // draft-agl-tls-chacha20poly1305-04, test vector 1
+    let mut out = Vec::new();
+    chacha20(&mut out, 32, 0, &[0; 32]);
+    assert_eq!(out[..], [
+        0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86, 0xbd, 0x28,
+        0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a, 0xa8, 0x36, 0xef, 0xcc, 0x8b, 0x77, 0x0d, 0xc7
+    ][..]);
+}
@@ -1,11 +1,78 @@
 extern crate libc;
 extern crate rand;
+extern crate tiny_keccak;
 extern crate newhope;
 extern crate cnewhope;
 
+#[cfg(not(feature = "capi"))] mod replay;
+
 use rand::{ Rng, OsRng, ChaChaRng };
+#[cfg(not(feature = "capi"))] use tiny_keccak::Keccak;
+#[cfg(not(feature = "capi"))] use newhope::poly;
+#[cfg(not(feature = "capi"))] use replay::{ ReplayRng, chacha20, replay };
 use newhope::params;
 
+
+// the Rust C ABI draws from the OS, not from a replayable randombytes
+#[cfg(not(feature = "capi"))]
+#[test]
+fn test_kex_replay() {
+    let mut rng = OsRng::new().unwrap();
+    let (mut seed, mut noisea, mut noiseb) = ([0; 32], [0; 32], [0; 32]);
+    rng.fill_bytes(&mut seed);
+    rng.fill_bytes(&mut noisea);
+    rng.fill_bytes(&mut noiseb);
+
+    // keygen: randombytes(seed), sha3-256(seed), randombytes(noiseseed),
+    // then chacha20 noise with nonce 0 (sk) and 1 (e)
+    let mut c_stream = seed.to_vec();
+    c_stream.extend_from_slice(&noisea);
+    let mut rs_stream = vec![0; 32];
+    let mut sha3 = Keccak::new_sha3_256();
+    sha3.update(&seed);
+    sha3.finalize(&mut rs_stream);
+    chacha20(&mut rs_stream, 4 * params::N, 0, &noisea);
+    chacha20(&mut rs_stream, 4 * params::N, 1, &noisea);
+
+    let mut senda_c = [0; cnewhope::SENDABYTES];
+    let mut ska_c = cnewhope::Poly::default();
+    replay(&c_stream, || unsafe { cnewhope::newhope_keygen(senda_c.as_mut_ptr(), &mut ska_c) });
+
+    let (mut ska, mut senda) = ([0; params::POLY_BYTES], [0; params::SENDABYTES]);
+    newhope::keygen(ReplayRng::new(rs_stream), &mut ska, &mut senda);
+
+    let mut ska_c_bytes = [0; params::POLY_BYTES];
+    poly::poly_tobytes(&ska_c.coeffs, &mut ska_c_bytes);
+    assert_eq!(&ska[..], &ska_c_bytes[..]);
+    assert_eq!(&senda[..], &senda_c[..]);
+
+    // sharedb: randombytes(noiseseed), chacha20 noise with nonce 0 (sp),
+    // 1 (ep), 2 (epp), then 32 bytes with nonce 3 for helprec
+    let mut rs_stream = Vec::new();
+    chacha20(&mut rs_stream, 4 * params::N, 0, &noiseb);
+    chacha20(&mut rs_stream, 4 * params::N, 1, &noiseb);
+    chacha20(&mut rs_stream, 4 * params::N, 2, &noiseb);
+    chacha20(&mut rs_stream, 32, 3, &noiseb);
+
+    let (mut keyb_c, mut sendb_c) = ([0; 32], [0; cnewhope::SENDBBYTES]);
+    replay(&noiseb, || unsafe {
+        cnewhope::newhope_sharedb(keyb_c.as_mut_ptr(), sendb_c.as_mut_ptr(), senda_c.as_ptr())
+    });
+
+    let (mut keyb, mut sendb) = ([0; 32], [0; params::SENDBBYTES]);
+    newhope::sharedb(ReplayRng::new(rs_stream), &mut keyb, &senda, &mut sendb);
+
+    assert_eq!(&sendb[..], &sendb_c[..]);
+    assert_eq!(keyb, keyb_c);
+
+    let (mut keya, mut keya_c) = ([0; 32], [0; 32]);
+    newhope::shareda(&mut keya, &ska, &sendb);
+    unsafe { cnewhope::newhope_shareda(keya_c.as_mut_ptr(), &ska_c, sendb_c.as_ptr()) };
+
+    assert_eq!(keya, keya_c);
+    assert_eq!(keya, keyb);
+}
+
 #[test]
 fn test_kex_rs() {
     let (mut ska, mut pka) = ([0; params::POLY_BYTES], [0; params::SENDABYTES]);
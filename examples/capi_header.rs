@@ -0,0 +1,5 @@
+extern crate newhope;
+
+fn main() {
+    print!("{}", newhope::capi::header());
+}
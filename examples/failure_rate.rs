@@ -0,0 +1,89 @@
+//! Estimate the reconciliation failure rate.
+//!
+//! ```text
+//! cargo run --release --features simulate --example failure_rate -- <trials> [k...]
+//! ```
+//!
+//! Runs `trials` handshakes for each noise parameter `k` (default: 16 and a
+//! few inflated values) on all cores, then fits the observed failure rates
+//! to extrapolate to the real parameters.
+
+extern crate rand;
+extern crate newhope;
+
+use std::{ env, thread };
+use rand::{ Rng, OsRng, ChaChaRng };
+use newhope::Q;
+use newhope::simulate::{ self, Stats, K };
+
+
+fn simulate(k: u32, trials: u64) -> Stats {
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1) as u64;
+
+    let handles = (0..threads)
+        .map(|i| {
+            let trials = trials / threads + if i < trials % threads { 1 } else { 0 };
+            thread::spawn(move || {
+                let mut rng = OsRng::new().unwrap().gen::<ChaChaRng>();
+                let mut stats = Stats::new();
+                simulate::run(&mut stats, k, trials, &mut rng);
+                stats
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut stats = Stats::new();
+    for handle in handles {
+        stats.merge(&handle.join().unwrap());
+    }
+    stats
+}
+
+fn print_histogram(stats: &Stats) {
+    const WIDTH: usize = 512;
+
+    let total = stats.histogram.iter().sum::<u64>() as f64;
+    for (i, bin) in stats.histogram.chunks(WIDTH).enumerate() {
+        let count = bin.iter().sum::<u64>();
+        if count > 0 {
+            let lo = (i * WIDTH) as isize - (Q / 2) as isize;
+            println!("    [{:6}, {:6})  {:.3e}", lo, lo + bin.len() as isize, count as f64 / total);
+        }
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let trials = args.next()
+        .map(|s| s.parse().expect("trials must be a number"))
+        .unwrap_or(100_000);
+    let mut ks = args
+        .map(|s| s.parse().expect("k must be a number"))
+        .collect::<Vec<u32>>();
+    if ks.is_empty() {
+        ks = vec![K, 32, 36, 40, 44, 48];
+    }
+
+    let mut points = Vec::new();
+    for &k in &ks {
+        let stats = simulate(k, trials);
+        println!(
+            "k = {:3}: {} failures / {} trials, rate {:.3e}, bit rate {:.3e}, sigma {:.1} (expected {:.1})",
+            k, stats.failures, stats.trials, stats.failure_rate(), stats.bit_failure_rate(),
+            stats.variance().sqrt(), simulate::variance(k).sqrt()
+        );
+        print_histogram(&stats);
+
+        if k != K {
+            points.push((stats.variance(), stats.bit_failure_rate()));
+        }
+    }
+
+    match simulate::extrapolate(&points, K) {
+        Some(p) => println!(
+            "extrapolated at k = {}: bit rate {:.3e}, handshake rate <= {:.3e} (2^{:.1})",
+            K, p, 256. * p, (256. * p).log2()
+        ),
+        None => println!("not enough failures observed to extrapolate")
+    }
+}
@@ -0,0 +1,41 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate rand;
+extern crate newhope;
+extern crate newhope_fuzz;
+
+use rand::{ SeedableRng, ChaChaRng };
+use newhope::{ POLY_BYTES, SENDABYTES, SENDBBYTES, PublicKey, Reply };
+use newhope_fuzz::FuzzRng;
+
+fuzz_target!(|data: &[u8]| {
+    let (alice, bob) = data.split_at(data.len() / 2);
+
+    // raw fuzzer bytes as noise: the messages must decode, but adversarial
+    // noise can legitimately break reconciliation
+    let (mut ska, mut pka) = ([0; POLY_BYTES], [0; SENDABYTES]);
+    let (mut keya, mut keyb) = ([0; 32], [0; 32]);
+    let mut sendb = [0; SENDBBYTES];
+
+    newhope::keygen(FuzzRng::new(alice), &mut ska, &mut pka);
+    assert!(PublicKey::from_bytes(&pka).is_some());
+
+    newhope::sharedb(FuzzRng::new(bob), &mut keyb, &pka, &mut sendb);
+    assert!(Reply::from_bytes(&sendb).is_some());
+
+    newhope::shareda(&mut keya, &ska, &sendb);
+
+    // honest noise from a seeded generator: the keys must agree
+    let seed = |bytes: &[u8]| {
+        let mut words = [0; 8];
+        for (i, &b) in bytes.iter().take(32).enumerate() {
+            words[i / 4] |= (b as u32) << (8 * (i % 4));
+        }
+        ChaChaRng::from_seed(&words)
+    };
+
+    newhope::keygen(seed(alice), &mut ska, &mut pka);
+    newhope::sharedb(seed(bob), &mut keyb, &pka, &mut sendb);
+    newhope::shareda(&mut keya, &ska, &sendb);
+    assert_eq!(keya, keyb);
+});
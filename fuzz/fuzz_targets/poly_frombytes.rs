@@ -0,0 +1,29 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate newhope;
+
+use newhope::{ N, Q, POLY_BYTES };
+use newhope::poly::{ poly_frombytes, poly_tobytes, poly_check };
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() != POLY_BYTES {
+        return;
+    }
+
+    let (mut p, mut p2) = ([0; N], [0; N]);
+    let mut bytes = [0; POLY_BYTES];
+
+    poly_frombytes(data, &mut p);
+    assert!(p.iter().all(|&c| c < 1 << 14));
+
+    poly_tobytes(&p, &mut bytes);
+    assert!(poly_check(&bytes));
+    poly_frombytes(&bytes, &mut p2);
+    for i in 0..N {
+        assert_eq!(p2[i] as usize, p[i] as usize % Q);
+    }
+
+    if poly_check(data) {
+        assert_eq!(&bytes[..], data);
+    }
+});
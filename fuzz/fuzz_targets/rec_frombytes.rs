@@ -0,0 +1,21 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate newhope;
+
+use newhope::{ N, RECBYTES };
+use newhope::newhope::{ rec_frombytes, rec_tobytes };
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() != RECBYTES {
+        return;
+    }
+
+    let mut c = [0; N];
+    let mut bytes = [0; RECBYTES];
+
+    rec_frombytes(data, &mut c);
+    assert!(c.iter().all(|&x| x < 4));
+
+    rec_tobytes(&c, &mut bytes);
+    assert_eq!(&bytes[..], data);
+});
@@ -0,0 +1,20 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate newhope;
+
+use newhope::{ POLY_BYTES, SENDBBYTES };
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() != POLY_BYTES + SENDBBYTES {
+        return;
+    }
+
+    let (sk, msg) = data.split_at(POLY_BYTES);
+    let mut ska = [0; POLY_BYTES];
+    let mut sendb = [0; SENDBBYTES];
+    ska.copy_from_slice(sk);
+    sendb.copy_from_slice(msg);
+
+    let mut key = [0; 32];
+    newhope::shareda(&mut key, &ska, &sendb);
+});
@@ -0,0 +1,23 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate newhope;
+extern crate newhope_fuzz;
+
+use newhope::{ SENDABYTES, SENDBBYTES, Reply };
+use newhope_fuzz::FuzzRng;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < SENDABYTES {
+        return;
+    }
+
+    let (pk, rng) = data.split_at(SENDABYTES);
+    let mut pka = [0; SENDABYTES];
+    pka.copy_from_slice(pk);
+
+    let mut key = [0; 32];
+    let mut msg = [0; SENDBBYTES];
+    newhope::sharedb(FuzzRng::new(rng), &mut key, &pka, &mut msg);
+
+    assert!(Reply::from_bytes(&msg).is_some());
+});
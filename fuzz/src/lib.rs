@@ -0,0 +1,36 @@
+extern crate rand;
+
+use rand::Rng;
+
+
+/// An `Rng` that replays fuzzer-provided bytes, then yields zeros.
+pub struct FuzzRng<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> FuzzRng<'a> {
+    pub fn new(data: &'a [u8]) -> FuzzRng<'a> {
+        FuzzRng { data }
+    }
+}
+
+impl<'a> Rng for FuzzRng<'a> {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0; 4];
+        self.fill_bytes(&mut buf);
+        u32::from(buf[0])
+            | u32::from(buf[1]) << 8
+            | u32::from(buf[2]) << 16
+            | u32::from(buf[3]) << 24
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let n = dest.len().min(self.data.len());
+        let (head, rest) = self.data.split_at(n);
+        dest[..n].copy_from_slice(head);
+        for b in &mut dest[n..] {
+            *b = 0;
+        }
+        self.data = rest;
+    }
+}
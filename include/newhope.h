@@ -0,0 +1,43 @@
+/* Generated by newhope::capi::header(), do not edit. */
+
+#ifndef NEWHOPE_H
+#define NEWHOPE_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define PARAM_N 1024
+#define PARAM_Q 12289
+
+#define NEWHOPE_SEEDBYTES 32
+#define NEWHOPE_RECBYTES 256
+#define NEWHOPE_SENDABYTES 1824
+#define NEWHOPE_SENDBBYTES 2048
+
+#define CRYPTO_SECRETKEYBYTES 1792
+#define CRYPTO_PUBLICKEYBYTES 1824
+#define CRYPTO_CIPHERTEXTBYTES 2048
+#define CRYPTO_BYTES 32
+
+typedef struct {
+  uint16_t coeffs[PARAM_N];
+} poly __attribute__ ((aligned (32)));
+
+/* Reference API: send is NEWHOPE_SENDABYTES, sk stays in NTT domain. */
+void newhope_keygen(unsigned char *send, poly *sk);
+void newhope_sharedb(unsigned char *sharedkey, unsigned char *send, const unsigned char *received);
+void newhope_shareda(unsigned char *sharedkey, const poly *ska, const unsigned char *received);
+
+/* KEM API: returns 0 on success, -1 on a malformed input or RNG failure. */
+int crypto_kem_keypair(unsigned char *pk, unsigned char *sk);
+int crypto_kem_enc(unsigned char *ct, unsigned char *ss, const unsigned char *pk);
+int crypto_kem_dec(unsigned char *ss, const unsigned char *ct, const unsigned char *sk);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,92 @@
+//! Keccak-based authenticated encryption.
+//!
+//! Encrypt-then-MAC built from KMAC256 (NIST SP 800-185): the keystream is
+//! KMAC256 of the nonce under `key`, and the tag KMAC256 of the nonce,
+//! associated data and ciphertext under the same key with a different
+//! customisation string. A nonce must never repeat under one key.
+
+use ::kdf::{ Kmac256, ct_eq };
+use ::error::Error;
+
+
+const STREAM_LABEL: &[u8] = b"newhope aead stream v1";
+const TAG_LABEL: &[u8] = b"newhope aead tag v1";
+
+pub const KEY_BYTES: usize = 32;
+pub const TAG_BYTES: usize = 32;
+
+fn keystream(key: &[u8; KEY_BYTES], nonce: &[u8], data: &mut [u8]) {
+    let mut stream = vec![0; data.len()];
+    let mut kmac = Kmac256::new(key, STREAM_LABEL);
+    kmac.update_string(nonce);
+    kmac.finalize(&mut stream);
+    for (x, k) in data.iter_mut().zip(&stream) {
+        *x ^= k;
+    }
+}
+
+fn tag(key: &[u8; KEY_BYTES], nonce: &[u8], ad: &[u8], ciphertext: &[u8]) -> [u8; TAG_BYTES] {
+    let mut tag = [0; TAG_BYTES];
+    let mut kmac = Kmac256::new(key, TAG_LABEL);
+    kmac.update_string(nonce);
+    kmac.update_string(ad);
+    kmac.update_string(ciphertext);
+    kmac.finalize(&mut tag);
+    tag
+}
+
+/// Encrypt `plaintext` and authenticate it with `ad`; returns the ciphertext
+/// followed by a `TAG_BYTES` tag.
+pub fn seal(key: &[u8; KEY_BYTES], nonce: &[u8], ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(plaintext.len() + TAG_BYTES);
+    out.extend_from_slice(plaintext);
+    keystream(key, nonce, &mut out);
+    let tag = tag(key, nonce, ad, &out);
+    out.extend_from_slice(&tag);
+    out
+}
+
+/// Check and decrypt the output of `seal`.
+pub fn open(key: &[u8; KEY_BYTES], nonce: &[u8], ad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+    if sealed.len() < TAG_BYTES {
+        return Err(Error::InvalidLength);
+    }
+    let (ciphertext, expected) = sealed.split_at(sealed.len() - TAG_BYTES);
+    if !ct_eq(&tag(key, nonce, ad, ciphertext), expected) {
+        return Err(Error::DecryptionFailed);
+    }
+
+    let mut plaintext = ciphertext.to_vec();
+    keystream(key, nonce, &mut plaintext);
+    Ok(plaintext)
+}
+
+
+#[test]
+fn test_aead() {
+    let key = [9; KEY_BYTES];
+    let sealed = seal(&key, b"nonce", b"header", b"attack at dawn");
+    assert_eq!(sealed.len(), 14 + TAG_BYTES);
+    assert_ne!(&sealed[..14], b"attack at dawn");
+    assert_eq!(open(&key, b"nonce", b"header", &sealed).unwrap(), b"attack at dawn");
+
+    // every input is authenticated
+    assert_eq!(open(&[8; KEY_BYTES], b"nonce", b"header", &sealed), Err(Error::DecryptionFailed));
+    assert_eq!(open(&key, b"noncf", b"header", &sealed), Err(Error::DecryptionFailed));
+    assert_eq!(open(&key, b"nonce", b"headers", &sealed), Err(Error::DecryptionFailed));
+    for i in 0..sealed.len() {
+        let mut bad = sealed.clone();
+        bad[i] ^= 0x80;
+        assert_eq!(open(&key, b"nonce", b"header", &bad), Err(Error::DecryptionFailed));
+    }
+    assert_eq!(open(&key, b"nonce", b"header", &sealed[1..]), Err(Error::DecryptionFailed));
+    assert_eq!(open(&key, b"nonce", b"header", &sealed[..TAG_BYTES - 1]), Err(Error::InvalidLength));
+
+    let empty = seal(&key, b"nonce", b"", b"");
+    assert_eq!(empty.len(), TAG_BYTES);
+    assert_eq!(open(&key, b"nonce", b"", &empty).unwrap(), b"");
+
+    // distinct nonces give unrelated keystreams
+    let other = seal(&key, b"nonce 2", b"header", b"attack at dawn");
+    assert_ne!(&other[..14], &sealed[..14]);
+}
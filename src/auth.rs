@@ -0,0 +1,338 @@
+//! Long-term signatures for authenticated handshakes.
+//!
+//! `Signer` and `Verifier` let a handshake party prove its identity by
+//! signing the transcript; any scheme can be plugged in. The built-in
+//! `MerkleSigner` needs nothing but the Keccak permutation: it is a Merkle
+//! tree of Winternitz one-time keys (w = 16, 32-byte hashes), in the manner of
+//! XMSS and LMS though compatible with neither. Each leaf signs once, so the
+//! signer is stateful and holds `2^height` signatures; its index must be
+//! persisted before a signature leaves the process.
+
+use rand::Rng;
+use byteorder::{ ByteOrder, BigEndian };
+use ::kdf::{ CShake256, FUNCTION_NAME, ct_eq, zeroize };
+use ::error::Error;
+
+
+pub trait Signer {
+    /// Sign `message`; a stateful signer may run out of signatures.
+    fn sign(&mut self, message: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+pub trait Verifier {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool;
+}
+
+
+const LABEL: &[u8] = b"newhope merkle signature v1";
+const N: usize = 32;
+const W: usize = 16;
+/// Message digits plus checksum digits.
+const CHAINS: usize = 2 * N + 3;
+/// Largest supported tree.
+pub const MAX_HEIGHT: u8 = 20;
+
+const TAG_SECRET: u8 = 0;
+const TAG_CHAIN: u8 = 1;
+const TAG_LEAF: u8 = 2;
+const TAG_NODE: u8 = 3;
+const TAG_MESSAGE: u8 = 4;
+
+/// cSHAKE256 over `tag | id | q | i | j | data`, where `q` is a leaf or node
+/// index, `i` a chain and `j` a step.
+fn hash(base: &CShake256, tag: u8, q: u32, i: u16, j: u8, data: &[&[u8]]) -> [u8; N] {
+    let mut buf = [0; 8];
+    buf[0] = tag;
+    BigEndian::write_u32(&mut buf[1..5], q);
+    BigEndian::write_u16(&mut buf[5..7], i);
+    buf[7] = j;
+
+    let mut cshake = base.clone();
+    cshake.update(&buf);
+    for d in data {
+        cshake.update(d);
+    }
+    let mut out = [0; N];
+    cshake.finalize(&mut out);
+    out
+}
+
+fn base(id: &[u8; N]) -> CShake256 {
+    let mut cshake = CShake256::new(FUNCTION_NAME, LABEL);
+    cshake.update(id);
+    cshake
+}
+
+fn chain(base: &CShake256, q: u32, i: usize, x: &mut [u8; N], from: usize, to: usize) {
+    for j in from..to {
+        *x = hash(base, TAG_CHAIN, q, i as u16, j as u8, &[x]);
+    }
+}
+
+/// Base-16 digits of the message digest, then of its checksum.
+fn digits(base: &CShake256, q: u32, message: &[u8]) -> [u8; CHAINS] {
+    let digest = hash(base, TAG_MESSAGE, q, 0, 0, &[message]);
+    let mut d = [0; CHAINS];
+    for (k, &b) in digest.iter().enumerate() {
+        d[2 * k] = b >> 4;
+        d[2 * k + 1] = b & 0xf;
+    }
+
+    let checksum = d[..2 * N].iter().map(|&x| (W - 1 - x as usize) as u16).sum::<u16>();
+    d[2 * N] = (checksum >> 8) as u8 & 0xf;
+    d[2 * N + 1] = (checksum >> 4) as u8 & 0xf;
+    d[2 * N + 2] = checksum as u8 & 0xf;
+    d
+}
+
+fn leaf(base: &CShake256, q: u32, ends: &[[u8; N]; CHAINS]) -> [u8; N] {
+    let ends = ends.iter().map(|x| &x[..]).collect::<Vec<_>>();
+    hash(base, TAG_LEAF, q, 0, 0, &ends)
+}
+
+
+/// Stateful hash-based signer.
+pub struct MerkleSigner {
+    seed: [u8; N],
+    id: [u8; N],
+    height: u8,
+    next: u32,
+    /// Heap-ordered tree: the root at 1, leaf `q` at `2^height + q`.
+    nodes: Vec<[u8; N]>
+}
+
+impl MerkleSigner {
+    /// A fresh key pair with `2^height` signatures; key generation hashes
+    /// about `1000 * 2^height` times.
+    pub fn generate<R: Rng>(mut rng: R, height: u8) -> Result<MerkleSigner, Error> {
+        let (mut seed, mut id) = ([0; N], [0; N]);
+        rng.fill_bytes(&mut seed);
+        rng.fill_bytes(&mut id);
+        let signer = MerkleSigner::from_seed(&seed, &id, height, 0);
+        zeroize(&mut seed);
+        signer
+    }
+
+    /// Rebuild a signer from its secret `seed`, public `id` and the index of
+    /// the next unused leaf; `Error::InvalidKey` if the height is over
+    /// `MAX_HEIGHT` or the index past the last leaf.
+    pub fn from_seed(seed: &[u8; N], id: &[u8; N], height: u8, next: u32) -> Result<MerkleSigner, Error> {
+        if height > MAX_HEIGHT || next as u64 > 1u64 << height {
+            return Err(Error::InvalidKey);
+        }
+
+        let base = base(id);
+        let leaves = 1usize << height;
+        let mut nodes = vec![[0; N]; 2 * leaves];
+        for q in 0..leaves {
+            let ends = MerkleSigner::ends(&base, seed, q as u32);
+            nodes[leaves + q] = leaf(&base, q as u32, &ends);
+        }
+        for r in (1..leaves).rev() {
+            nodes[r] = hash(&base, TAG_NODE, r as u32, 0, 0, &[&nodes[2 * r], &nodes[2 * r + 1]]);
+        }
+
+        Ok(MerkleSigner { seed: *seed, id: *id, height, next, nodes })
+    }
+
+    fn secret(base: &CShake256, seed: &[u8; N], q: u32, i: usize) -> [u8; N] {
+        hash(base, TAG_SECRET, q, i as u16, 0, &[seed])
+    }
+
+    fn ends(base: &CShake256, seed: &[u8; N], q: u32) -> [[u8; N]; CHAINS] {
+        let mut ends = [[0; N]; CHAINS];
+        for (i, x) in ends.iter_mut().enumerate() {
+            *x = MerkleSigner::secret(base, seed, q, i);
+            chain(base, q, i, x, 0, W - 1);
+        }
+        ends
+    }
+
+    pub fn verifier(&self) -> MerkleVerifier {
+        MerkleVerifier { id: self.id, height: self.height, root: self.nodes[1] }
+    }
+
+    /// Secret seed, for `from_seed`. Whoever holds it can sign as this key,
+    /// so keep it as secret as the key itself.
+    pub fn seed(&self) -> &[u8; N] {
+        &self.seed
+    }
+
+    /// Public tree identifier, for `from_seed`.
+    pub fn id(&self) -> &[u8; N] {
+        &self.id
+    }
+
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// Index of the next unused leaf, to be persisted with the seed.
+    pub fn next_index(&self) -> u32 {
+        self.next
+    }
+
+    pub fn remaining(&self) -> u64 {
+        (1u64 << self.height).saturating_sub(self.next as u64)
+    }
+}
+
+impl Drop for MerkleSigner {
+    fn drop(&mut self) {
+        zeroize(&mut self.seed);
+    }
+}
+
+impl Signer for MerkleSigner {
+    /// `q (4 bytes) | CHAINS * 32 bytes | height * 32 bytes of path`.
+    fn sign(&mut self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.remaining() == 0 {
+            return Err(Error::KeyExhausted);
+        }
+        let q = self.next;
+        self.next += 1;
+
+        let base = base(&self.id);
+        let d = digits(&base, q, message);
+        let mut signature = vec![0; 4];
+        BigEndian::write_u32(&mut signature, q);
+
+        for (i, &digit) in d.iter().enumerate() {
+            let mut x = MerkleSigner::secret(&base, &self.seed, q, i);
+            chain(&base, q, i, &mut x, 0, digit as usize);
+            signature.extend_from_slice(&x);
+        }
+
+        let mut r = (1usize << self.height) + q as usize;
+        while r > 1 {
+            signature.extend_from_slice(&self.nodes[r ^ 1]);
+            r >>= 1;
+        }
+        Ok(signature)
+    }
+}
+
+
+/// Public key of a `MerkleSigner`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleVerifier {
+    id: [u8; N],
+    height: u8,
+    root: [u8; N]
+}
+
+impl MerkleVerifier {
+    pub const BYTES: usize = 1 + 2 * N;
+
+    /// `height | id | root`.
+    pub fn to_bytes(&self) -> [u8; MerkleVerifier::BYTES] {
+        let mut out = [0; MerkleVerifier::BYTES];
+        out[0] = self.height;
+        out[1..1 + N].copy_from_slice(&self.id);
+        out[1 + N..].copy_from_slice(&self.root);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<MerkleVerifier, Error> {
+        if bytes.len() != MerkleVerifier::BYTES {
+            return Err(Error::InvalidLength);
+        }
+        if bytes[0] > MAX_HEIGHT {
+            return Err(Error::InvalidKey);
+        }
+
+        let (mut id, mut root) = ([0; N], [0; N]);
+        id.copy_from_slice(&bytes[1..1 + N]);
+        root.copy_from_slice(&bytes[1 + N..]);
+        Ok(MerkleVerifier { id, height: bytes[0], root })
+    }
+}
+
+impl Verifier for MerkleVerifier {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        let height = self.height as usize;
+        if signature.len() != 4 + (CHAINS + height) * N {
+            return false;
+        }
+        let q = BigEndian::read_u32(&signature[..4]);
+        if q as u64 >= 1u64 << height {
+            return false;
+        }
+
+        let base = base(&self.id);
+        let d = digits(&base, q, message);
+        let (ots, path) = signature[4..].split_at(CHAINS * N);
+
+        let mut ends = [[0; N]; CHAINS];
+        for (i, (x, s)) in ends.iter_mut().zip(ots.chunks(N)).enumerate() {
+            x.copy_from_slice(s);
+            chain(&base, q, i, x, d[i] as usize, W - 1);
+        }
+
+        let mut node = leaf(&base, q, &ends);
+        let mut r = (1usize << height) + q as usize;
+        for sibling in path.chunks(N) {
+            node = if r & 1 == 0 {
+                hash(&base, TAG_NODE, (r >> 1) as u32, 0, 0, &[&node, sibling])
+            } else {
+                hash(&base, TAG_NODE, (r >> 1) as u32, 0, 0, &[sibling, &node])
+            };
+            r >>= 1;
+        }
+
+        ct_eq(&node, &self.root)
+    }
+}
+
+
+#[test]
+fn test_digits() {
+    let d = digits(&base(&[0; N]), 0, b"message");
+    assert!(d.iter().all(|&x| (x as usize) < W));
+
+    let checksum = d[..2 * N].iter().map(|&x| W - 1 - x as usize).sum::<usize>();
+    assert_eq!(checksum, ((d[2 * N] as usize) << 8) | ((d[2 * N + 1] as usize) << 4) | d[2 * N + 2] as usize);
+}
+
+#[test]
+fn test_merkle_signature() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x6d65, 0x726b, 0x6c65]);
+    let mut signer = MerkleSigner::generate(&mut rng, 3).unwrap();
+    let verifier = signer.verifier();
+    assert_eq!(MerkleVerifier::from_bytes(&verifier.to_bytes()), Ok(verifier.clone()));
+    assert_eq!(signer.remaining(), 8);
+
+    let mut signatures = Vec::new();
+    for k in 0..8u8 {
+        let signature = signer.sign(&[k]).unwrap();
+        assert_eq!(signature.len(), 4 + (CHAINS + 3) * N);
+        assert!(verifier.verify(&[k], &signature));
+        assert!(!verifier.verify(&[k + 1], &signature));
+        signatures.push(signature);
+    }
+    assert_eq!(signer.sign(b"one too many"), Err(Error::KeyExhausted));
+    assert_eq!(signer.next_index(), 8);
+
+    // every byte of the signature matters
+    let mut rng = ChaChaRng::from_seed(&[0x6279, 0x7465]);
+    for _ in 0..16 {
+        let mut bad = signatures[5].clone();
+        let i = rng.gen_range(0, bad.len());
+        bad[i] ^= 1 << rng.gen_range(0, 8);
+        assert!(!verifier.verify(&[5], &bad));
+    }
+    assert!(!verifier.verify(&[5], &signatures[5][1..]));
+
+    // a signer rebuilt from its seed has the same public key
+    let rebuilt = MerkleSigner::from_seed(signer.seed(), signer.id(), signer.height(), 8).unwrap();
+    assert_eq!(rebuilt.verifier(), verifier);
+    let other = MerkleSigner::generate(&mut rng, 3).unwrap().verifier();
+
+    // persisted state is checked rather than trusted
+    assert!(MerkleSigner::from_seed(signer.seed(), signer.id(), 3, 9).is_err());
+    assert!(MerkleSigner::from_seed(signer.seed(), signer.id(), MAX_HEIGHT + 1, 0).is_err());
+    assert!(MerkleSigner::generate(&mut rng, MAX_HEIGHT + 1).is_err());
+    assert!(!other.verify(&[0], &signatures[0]));
+}
@@ -0,0 +1,248 @@
+//! C ABI compatible with the reference implementation.
+//!
+//! `cargo rustc --lib --release --features capi --crate-type cdylib` (or
+//! `staticlib`) builds the library; include `include/newhope.h`, which is generated by
+//! [`header`](fn.header.html) (`cargo run --features capi --example capi_header`).
+
+use std::process;
+use std::slice;
+use rand::{ Rng, OsRng, ChaChaRng };
+use ::params::{ N, Q, POLY_BYTES, SEEDBYTES, RECBYTES, SENDABYTES, SENDBBYTES };
+use ::message::{ PublicKey, Reply };
+
+pub const CRYPTO_SECRETKEYBYTES: usize = POLY_BYTES;
+pub const CRYPTO_PUBLICKEYBYTES: usize = SENDABYTES;
+pub const CRYPTO_CIPHERTEXTBYTES: usize = SENDBBYTES;
+pub const CRYPTO_BYTES: usize = 32;
+
+
+/// The reference `poly`, holding Alice's secret key between the two calls.
+#[repr(C, align(32))]
+pub struct Poly {
+    pub coeffs: [u16; N]
+}
+
+fn rng() -> Option<ChaChaRng> {
+    OsRng::new().ok().map(|mut r| r.gen::<ChaChaRng>())
+}
+
+unsafe fn array<'a, T>(p: *const u8) -> &'a T {
+    &*(p as *const T)
+}
+
+unsafe fn array_mut<'a, T>(p: *mut u8) -> &'a mut T {
+    &mut *(p as *mut T)
+}
+
+/// `rec` ORs key bits into its output, so clear the caller's buffer first.
+unsafe fn key<'a>(p: *mut u8) -> &'a mut [u8; 32] {
+    let key = array_mut::<[u8; 32]>(p);
+    *key = [0; 32];
+    key
+}
+
+
+/// Alice's first step.
+///
+/// # Safety
+///
+/// `send` must point to `NEWHOPE_SENDABYTES` writable bytes and `sk` to a valid `poly`.
+#[no_mangle]
+pub unsafe extern "C" fn newhope_keygen(send: *mut u8, sk: *mut Poly) {
+    let mut rng = rng().unwrap_or_else(|| process::abort());
+    let send = slice::from_raw_parts_mut(send, SENDABYTES);
+    let (pk, nonce) = send.split_at_mut(POLY_BYTES);
+    let mut pka = [0; N];
+
+    rng.fill_bytes(nonce);
+    ::newhope::keygen(&mut (*sk).coeffs, &mut pka, nonce, rng);
+    ::poly::poly_tobytes(&pka, pk);
+}
+
+/// Bob's response.
+///
+/// # Safety
+///
+/// `sharedkey` must point to 32 writable bytes, `send` to `NEWHOPE_SENDBBYTES`
+/// writable bytes and `received` to `NEWHOPE_SENDABYTES` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn newhope_sharedb(sharedkey: *mut u8, send: *mut u8, received: *const u8) {
+    let rng = rng().unwrap_or_else(|| process::abort());
+    ::sharedb(rng, key(sharedkey), array(received), array_mut(send));
+}
+
+/// Alice's second step.
+///
+/// # Safety
+///
+/// `sharedkey` must point to 32 writable bytes, `ska` to the `poly` filled by
+/// `newhope_keygen` and `received` to `NEWHOPE_SENDBBYTES` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn newhope_shareda(sharedkey: *mut u8, ska: *const Poly, received: *const u8) {
+    let mut sk = [0; POLY_BYTES];
+    ::poly::poly_tobytes(&(*ska).coeffs, &mut sk);
+    ::shareda(key(sharedkey), &sk, array(received));
+}
+
+/// KEM key generation; `sk` is the packed secret polynomial.
+///
+/// # Safety
+///
+/// `pk` and `sk` must be null or point to `CRYPTO_PUBLICKEYBYTES` and
+/// `CRYPTO_SECRETKEYBYTES` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_kem_keypair(pk: *mut u8, sk: *mut u8) -> i32 {
+    if pk.is_null() || sk.is_null() {
+        return -1;
+    }
+
+    match rng() {
+        Some(rng) => {
+            ::keygen(rng, array_mut(sk), array_mut(pk));
+            0
+        },
+        None => -1
+    }
+}
+
+/// KEM encapsulation, rejecting a malformed `pk`.
+///
+/// # Safety
+///
+/// Non-null pointers must cover `CRYPTO_CIPHERTEXTBYTES`, `CRYPTO_BYTES` and
+/// `CRYPTO_PUBLICKEYBYTES` bytes respectively.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_kem_enc(ct: *mut u8, ss: *mut u8, pk: *const u8) -> i32 {
+    if ct.is_null() || ss.is_null() || pk.is_null() {
+        return -1;
+    }
+    if PublicKey::from_bytes(slice::from_raw_parts(pk, SENDABYTES)).is_none() {
+        return -1;
+    }
+
+    match rng() {
+        Some(rng) => {
+            ::sharedb(rng, key(ss), array(pk), array_mut(ct));
+            0
+        },
+        None => -1
+    }
+}
+
+/// KEM decapsulation, rejecting a malformed `ct`.
+///
+/// # Safety
+///
+/// Non-null pointers must cover `CRYPTO_BYTES`, `CRYPTO_CIPHERTEXTBYTES` and
+/// `CRYPTO_SECRETKEYBYTES` bytes respectively.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_kem_dec(ss: *mut u8, ct: *const u8, sk: *const u8) -> i32 {
+    if ss.is_null() || ct.is_null() || sk.is_null() {
+        return -1;
+    }
+    if Reply::from_bytes(slice::from_raw_parts(ct, SENDBBYTES)).is_none() {
+        return -1;
+    }
+
+    ::shareda(key(ss), array(sk), array(ct));
+    0
+}
+
+
+/// C header declaring the exported functions.
+pub fn header() -> String {
+    format!(
+r#"/* Generated by newhope::capi::header(), do not edit. */
+
+#ifndef NEWHOPE_H
+#define NEWHOPE_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {{
+#endif
+
+#define PARAM_N {n}
+#define PARAM_Q {q}
+
+#define NEWHOPE_SEEDBYTES {seed}
+#define NEWHOPE_RECBYTES {rec}
+#define NEWHOPE_SENDABYTES {senda}
+#define NEWHOPE_SENDBBYTES {sendb}
+
+#define CRYPTO_SECRETKEYBYTES {sk}
+#define CRYPTO_PUBLICKEYBYTES {pk}
+#define CRYPTO_CIPHERTEXTBYTES {ct}
+#define CRYPTO_BYTES {ss}
+
+typedef struct {{
+  uint16_t coeffs[PARAM_N];
+}} poly __attribute__ ((aligned (32)));
+
+/* Reference API: send is NEWHOPE_SENDABYTES, sk stays in NTT domain. */
+void newhope_keygen(unsigned char *send, poly *sk);
+void newhope_sharedb(unsigned char *sharedkey, unsigned char *send, const unsigned char *received);
+void newhope_shareda(unsigned char *sharedkey, const poly *ska, const unsigned char *received);
+
+/* KEM API: returns 0 on success, -1 on a malformed input or RNG failure. */
+int crypto_kem_keypair(unsigned char *pk, unsigned char *sk);
+int crypto_kem_enc(unsigned char *ct, unsigned char *ss, const unsigned char *pk);
+int crypto_kem_dec(unsigned char *ss, const unsigned char *ct, const unsigned char *sk);
+
+#ifdef __cplusplus
+}}
+#endif
+
+#endif
+"#,
+        n = N, q = Q,
+        seed = SEEDBYTES, rec = RECBYTES, senda = SENDABYTES, sendb = SENDBBYTES,
+        sk = CRYPTO_SECRETKEYBYTES, pk = CRYPTO_PUBLICKEYBYTES,
+        ct = CRYPTO_CIPHERTEXTBYTES, ss = CRYPTO_BYTES
+    )
+}
+
+
+#[test]
+fn test_header() {
+    assert_eq!(header(), include_str!("../include/newhope.h"));
+}
+
+#[test]
+fn test_reference_api() {
+    let (mut senda, mut sendb) = ([0; SENDABYTES], [0; SENDBBYTES]);
+    let (mut keya, mut keyb) = ([0xff; 32], [0xff; 32]);
+    let mut ska = Poly { coeffs: [0; N] };
+
+    unsafe {
+        newhope_keygen(senda.as_mut_ptr(), &mut ska);
+        newhope_sharedb(keyb.as_mut_ptr(), sendb.as_mut_ptr(), senda.as_ptr());
+        newhope_shareda(keya.as_mut_ptr(), &ska, sendb.as_ptr());
+    }
+
+    assert!(keya != [0; 32]);
+    assert_eq!(keya, keyb);
+}
+
+#[test]
+fn test_kem_api() {
+    let (mut pk, mut sk) = ([0; CRYPTO_PUBLICKEYBYTES], [0; CRYPTO_SECRETKEYBYTES]);
+    let mut ct = [0; CRYPTO_CIPHERTEXTBYTES];
+    let (mut ssa, mut ssb) = ([0xff; CRYPTO_BYTES], [0xff; CRYPTO_BYTES]);
+
+    unsafe {
+        assert_eq!(crypto_kem_keypair(pk.as_mut_ptr(), sk.as_mut_ptr()), 0);
+        assert_eq!(crypto_kem_enc(ct.as_mut_ptr(), ssb.as_mut_ptr(), pk.as_ptr()), 0);
+        assert_eq!(crypto_kem_dec(ssa.as_mut_ptr(), ct.as_ptr(), sk.as_ptr()), 0);
+    }
+
+    assert!(ssa != [0; 32]);
+    assert_eq!(ssa, ssb);
+
+    let bad = [0xff; CRYPTO_PUBLICKEYBYTES];
+    unsafe {
+        assert_eq!(crypto_kem_enc(ct.as_mut_ptr(), ssb.as_mut_ptr(), bad.as_ptr()), -1);
+        assert_eq!(crypto_kem_dec(ssa.as_mut_ptr(), ::std::ptr::null(), sk.as_ptr()), -1);
+    }
+}
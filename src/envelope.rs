@@ -0,0 +1,494 @@
+//! Public-key encryption of arbitrary messages.
+//!
+//! `seal` picks a random 32-byte seed `m`, runs `sharedb` against the
+//! recipient's public key, with the parameter set the key was made for and
+//! randomness derived from `m` and the key, and masks `m` with the shared
+//! key; the message is encrypted with `aead` under a key derived from `m`.
+//! `open` runs `shareda` with the secret key, unmasks `m` and repeats the
+//! `sharedb` step, whichever supported sampler the header names; a header
+//! that doesn't come out identical is rejected before any key is used. This
+//! is the Fujisaki-Okamoto transform: NewHope keys were designed for one
+//! exchange, and without the check anyone who learned whether crafted
+//! headers opened could recover a long-term secret key. Opening thus needs
+//! the public key too. `Sealer` and `Opener` do the same over `Write` and
+//! `Read`, a chunk at a time. The container is:
+//!
+//! ```text
+//! header: "NHSEAL" | version (1) | sampler (1) | reply (SENDBBYTES) | masked m (32)
+//! chunk:  sealed plaintext | 32-byte tag
+//! ```
+//!
+//! followed by chunks of `CHUNK_BYTES` plaintext each, the last holding the
+//! remaining 0 to `CHUNK_BYTES` bytes. The chunk key is KMAC256 of the whole
+//! header under `m`, and each chunk's nonce is its index as eight big-endian
+//! bytes and a byte that is 1 for the last chunk, 0 otherwise, so reordered,
+//! dropped or appended chunks fail to open.
+//!
+//! `Opener` releases each chunk once it verifies, before later ones are
+//! checked; only reaching the end proves the message whole.
+
+use std::io::{ self, Read, Write };
+use std::cmp;
+use rand::{ Rng, SeedableRng, ChaChaRng };
+use byteorder::{ ByteOrder, BigEndian, LittleEndian };
+use ::params::{ POLY_BYTES, SENDBBYTES, Algorithm };
+use ::message::{ PublicKey, Reply };
+use ::workspace::Workspace;
+use ::kdf::{ CShake256, FUNCTION_NAME, ct_eq };
+use ::error::Error;
+use ::{ aead, frame, kdf };
+
+
+const MAGIC: &[u8] = b"NHSEAL";
+pub const VERSION: u8 = 1;
+pub const HEADER_BYTES: usize = 6 + 1 + 1 + SENDBBYTES + SEED_BYTES;
+const SEED_BYTES: usize = 32;
+/// Plaintext bytes in every chunk but the last.
+pub const CHUNK_BYTES: usize = 64 * 1024;
+const SEALED_CHUNK_BYTES: usize = CHUNK_BYTES + aead::TAG_BYTES;
+
+const LABEL: &[u8] = b"newhope envelope v1";
+const COINS_LABEL: &[u8] = b"newhope envelope coins v1";
+
+fn nonce(index: u64, last: bool) -> [u8; 9] {
+    let mut nonce = [0; 9];
+    BigEndian::write_u64(&mut nonce[..8], index);
+    nonce[8] = last as u8;
+    nonce
+}
+
+fn chunk_key(key: &[u8; 32], header: &[u8]) -> [u8; aead::KEY_BYTES] {
+    let mut chunk_key = [0; aead::KEY_BYTES];
+    kdf::export(key, LABEL, header, &mut chunk_key);
+    chunk_key
+}
+
+/// The header for `m`: `sharedb` with coins derived from `m`, and `m`
+/// masked with the shared key.
+fn encapsulate(alg: Algorithm, pk: &PublicKey, m: &[u8; SEED_BYTES]) -> Vec<u8> {
+    let mut coins = [0; 32];
+    let mut cshake = CShake256::new(FUNCTION_NAME, COINS_LABEL);
+    cshake.update_string(&[frame::sampler(alg)]);
+    cshake.update_string(&pk.0);
+    cshake.update_string(m);
+    cshake.finalize(&mut coins);
+    let mut seed = [0; 8];
+    LittleEndian::read_u32_into(&coins, &mut seed);
+    coins.fill(0);
+
+    let (mut key, mut msg) = ([0; 32], [0; SENDBBYTES]);
+    ::sharedb_alg(&mut Workspace::new(), alg, ChaChaRng::from_seed(&seed), &mut key, &pk.0, &mut msg);
+    seed.fill(0);
+
+    let mut header = Vec::with_capacity(HEADER_BYTES);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&[VERSION, frame::sampler(alg)]);
+    header.extend_from_slice(&msg);
+    header.extend(m.iter().zip(key.iter()).map(|(x, k)| x ^ k));
+    key.fill(0);
+    header
+}
+
+/// Header and chunk key for a new message to `pk`, made for `alg`.
+fn seal_header<R: Rng>(mut rng: R, alg: Algorithm, pk: &PublicKey) -> Result<(Vec<u8>, [u8; aead::KEY_BYTES]), Error> {
+    if !alg.is_supported() {
+        return Err(Error::UnsupportedAlgorithm);
+    }
+    let mut m = [0; SEED_BYTES];
+    rng.fill_bytes(&mut m);
+    let header = encapsulate(alg, pk, &m);
+    let chunk_key = chunk_key(&m, &header);
+    m.fill(0);
+    Ok((header, chunk_key))
+}
+
+/// Chunk key of the message with `header`, once `header` has been checked
+/// by encapsulating again.
+fn open_header(sk: &[u8; POLY_BYTES], pk: &PublicKey, header: &[u8]) -> Result<[u8; aead::KEY_BYTES], Error> {
+    if header.len() != HEADER_BYTES {
+        return Err(Error::InvalidLength);
+    }
+    if &header[..6] != MAGIC {
+        return Err(Error::InvalidEncoding);
+    }
+    if header[6] != VERSION {
+        return Err(Error::UnsupportedVersion);
+    }
+    let alg = match frame::from_sampler(header[7]) {
+        Some(alg) if alg.is_supported() => alg,
+        _ => return Err(Error::UnsupportedAlgorithm)
+    };
+    let msg = Reply::from_bytes(&header[8..8 + SENDBBYTES]).ok_or(Error::InvalidKey)?;
+
+    let mut key = [0; 32];
+    ::shareda(&mut key, sk, &msg.0);
+    let mut m = [0; SEED_BYTES];
+    for ((m, x), k) in m.iter_mut().zip(&header[8 + SENDBBYTES..]).zip(key.iter()) {
+        *m = x ^ k;
+    }
+    key.fill(0);
+
+    let result = if ct_eq(&encapsulate(alg, pk, &m), header) {
+        Ok(chunk_key(&m, header))
+    } else {
+        Err(Error::DecryptionFailed)
+    };
+    m.fill(0);
+    result
+}
+
+/// Encrypt `plaintext` to the holder of `pk`'s secret key; `alg` is the
+/// parameter set `pk` was made for.
+pub fn seal<R: Rng>(rng: R, alg: Algorithm, pk: &PublicKey, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let (mut out, key) = seal_header(rng, alg, pk)?;
+    let chunks = cmp::max(1, plaintext.len().div_ceil(CHUNK_BYTES));
+    out.reserve(plaintext.len() + chunks * aead::TAG_BYTES);
+    for i in 0..chunks {
+        let chunk = &plaintext[i * CHUNK_BYTES..cmp::min(plaintext.len(), (i + 1) * CHUNK_BYTES)];
+        out.extend_from_slice(&aead::seal(&key, &nonce(i as u64, i + 1 == chunks), b"", chunk));
+    }
+    Ok(out)
+}
+
+/// Check and decrypt the output of `seal` or `Sealer`; `pk` is the public
+/// key that goes with `sk`.
+pub fn open(sk: &[u8; POLY_BYTES], pk: &PublicKey, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    if ciphertext.len() < HEADER_BYTES + aead::TAG_BYTES {
+        return Err(Error::InvalidLength);
+    }
+    let (header, body) = ciphertext.split_at(HEADER_BYTES);
+    let key = open_header(sk, pk, header)?;
+
+    let chunks = body.chunks(SEALED_CHUNK_BYTES).collect::<Vec<_>>();
+    let mut plaintext = Vec::with_capacity(body.len());
+    for (i, chunk) in chunks.iter().enumerate() {
+        let last = i + 1 == chunks.len();
+        plaintext.extend_from_slice(&aead::open(&key, &nonce(i as u64, last), b"", chunk)
+            .map_err(|_| Error::DecryptionFailed)?);
+    }
+    Ok(plaintext)
+}
+
+
+fn invalid_data(err: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Encrypts everything written to it into `inner`; call `finish` at the end.
+pub struct Sealer<W: Write> {
+    inner: W,
+    key: [u8; aead::KEY_BYTES],
+    index: u64,
+    buf: Vec<u8>
+}
+
+impl<W: Write> Sealer<W> {
+    /// Write the header for a message to `pk`, made for `alg`.
+    pub fn new<R: Rng>(rng: R, alg: Algorithm, pk: &PublicKey, mut inner: W) -> io::Result<Sealer<W>> {
+        let (header, key) = seal_header(rng, alg, pk).map_err(invalid_data)?;
+        inner.write_all(&header)?;
+        Ok(Sealer { inner, key, index: 0, buf: Vec::with_capacity(CHUNK_BYTES) })
+    }
+
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        let sealed = aead::seal(&self.key, &nonce(self.index, last), b"", &self.buf);
+        self.inner.write_all(&sealed)?;
+        self.index += 1;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Write the last chunk; without it the message won't open.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk(true)?;
+        self.inner.flush()?;
+        self.key = [0; aead::KEY_BYTES];
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Sealer<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        // a full chunk is only written once more data shows it isn't the last
+        if self.buf.len() == CHUNK_BYTES && !data.is_empty() {
+            self.write_chunk(false)?;
+        }
+        let n = cmp::min(data.len(), CHUNK_BYTES - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+
+/// Decrypts a sealed message read from `inner`.
+pub struct Opener<R: Read> {
+    inner: R,
+    key: [u8; aead::KEY_BYTES],
+    index: u64,
+    /// Sealed bytes read ahead, to tell the last chunk from the others.
+    sealed: Vec<u8>,
+    plaintext: Vec<u8>,
+    pos: usize,
+    done: bool,
+    broken: bool
+}
+
+impl<R: Read> Opener<R> {
+    /// Read and check the header, and recover the message key.
+    pub fn new(sk: &[u8; POLY_BYTES], pk: &PublicKey, mut inner: R) -> io::Result<Opener<R>> {
+        let mut header = [0; HEADER_BYTES];
+        inner.read_exact(&mut header)?;
+        let key = open_header(sk, pk, &header).map_err(invalid_data)?;
+        Ok(Opener {
+            inner, key,
+            index: 0,
+            sealed: Vec::with_capacity(SEALED_CHUNK_BYTES + 1),
+            plaintext: Vec::new(),
+            pos: 0,
+            done: false,
+            broken: false
+        })
+    }
+
+    /// Read up to one byte past a full chunk, or to the end.
+    fn fill(&mut self) -> io::Result<()> {
+        let mut buf = [0; 4096];
+        while self.sealed.len() <= SEALED_CHUNK_BYTES {
+            let want = cmp::min(buf.len(), SEALED_CHUNK_BYTES + 1 - self.sealed.len());
+            match self.inner.read(&mut buf[..want]) {
+                Ok(0) => break,
+                Ok(n) => self.sealed.extend_from_slice(&buf[..n]),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(())
+    }
+
+    fn next_chunk(&mut self) -> io::Result<()> {
+        self.fill()?;
+        let last = self.sealed.len() <= SEALED_CHUNK_BYTES;
+        let len = cmp::min(self.sealed.len(), SEALED_CHUNK_BYTES);
+        self.plaintext = aead::open(&self.key, &nonce(self.index, last), b"", &self.sealed[..len])
+            .map_err(|_| invalid_data(Error::DecryptionFailed))?;
+        self.sealed.drain(..len);
+        self.pos = 0;
+        self.index += 1;
+        self.done = last;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Opener<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.broken {
+            return Err(invalid_data(Error::DecryptionFailed));
+        }
+        while self.pos == self.plaintext.len() {
+            if self.done {
+                return Ok(0);
+            }
+            if let Err(err) = self.next_chunk() {
+                self.broken = true;
+                return Err(err);
+            }
+        }
+
+        let n = cmp::min(out.len(), self.plaintext.len() - self.pos);
+        out[..n].copy_from_slice(&self.plaintext[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+
+#[cfg(test)]
+fn key_pair() -> ([u8; POLY_BYTES], PublicKey) {
+    use rand::{ SeedableRng, ChaChaRng };
+    use ::params::SENDABYTES;
+
+    let mut rng = ChaChaRng::from_seed(&[0x6b65, 0x79]);
+    let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+    ::keygen(&mut rng, &mut sk, &mut pk);
+    (sk, PublicKey(pk))
+}
+
+#[test]
+fn test_envelope() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x656e, 0x76]);
+    let (sk, pk) = key_pair();
+    for &len in &[0, 1, 1000, CHUNK_BYTES - 1, CHUNK_BYTES, CHUNK_BYTES + 1, 2 * CHUNK_BYTES + 5] {
+        let plaintext = (0..len).map(|i| (i % 253) as u8).collect::<Vec<_>>();
+        let ciphertext = seal(&mut rng, Algorithm::CURRENT, &pk, &plaintext).unwrap();
+        let chunks = cmp::max(1, len.div_ceil(CHUNK_BYTES));
+        assert_eq!(ciphertext.len(), HEADER_BYTES + len + chunks * aead::TAG_BYTES);
+        assert_eq!(open(&sk, &pk, &ciphertext).unwrap(), plaintext);
+    }
+
+    // two seals of one message differ
+    assert_ne!(seal(&mut rng, Algorithm::CURRENT, &pk, b"msg").unwrap(), seal(&mut rng, Algorithm::CURRENT, &pk, b"msg").unwrap());
+}
+
+#[test]
+fn test_envelope_stream() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x7374, 0x7265, 0x616d]);
+    let (sk, pk) = key_pair();
+    let plaintext = (0..3 * CHUNK_BYTES + 77).map(|_| rng.gen()).collect::<Vec<u8>>();
+
+    // odd-sized writes and reads, both ways compatible with seal and open
+    let mut sealer = Sealer::new(&mut rng, Algorithm::CURRENT, &pk, Vec::new()).unwrap();
+    for piece in plaintext.chunks(10_007) {
+        sealer.write_all(piece).unwrap();
+    }
+    let ciphertext = sealer.finish().unwrap();
+    assert_eq!(open(&sk, &pk, &ciphertext).unwrap(), plaintext);
+
+    let ciphertext = seal(&mut rng, Algorithm::CURRENT, &pk, &plaintext).unwrap();
+    let mut opener = Opener::new(&sk, &pk, &ciphertext[..]).unwrap();
+    let (mut opened, mut buf) = (Vec::new(), [0; 999]);
+    loop {
+        let n = opener.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        opened.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(opened, plaintext);
+
+    // an exact number of chunks, and nothing at all
+    for &len in &[CHUNK_BYTES, 0] {
+        let mut sealer = Sealer::new(&mut rng, Algorithm::CURRENT, &pk, Vec::new()).unwrap();
+        sealer.write_all(&plaintext[..len]).unwrap();
+        let ciphertext = sealer.finish().unwrap();
+        let mut opened = Vec::new();
+        Opener::new(&sk, &pk, &ciphertext[..]).unwrap().read_to_end(&mut opened).unwrap();
+        assert_eq!(opened, &plaintext[..len]);
+    }
+}
+
+#[test]
+fn test_envelope_tamper() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x7461, 0x6d70]);
+    let (sk, pk) = key_pair();
+    let plaintext = vec![0x5a; 2 * CHUNK_BYTES + 10];
+    let ciphertext = seal(&mut rng, Algorithm::CURRENT, &pk, &plaintext).unwrap();
+
+    let stream_open = |ciphertext: &[u8]| -> io::Result<Vec<u8>> {
+        let mut opened = Vec::new();
+        Opener::new(&sk, &pk, ciphertext)?.read_to_end(&mut opened)?;
+        Ok(opened)
+    };
+    let fails = |ciphertext: &[u8]| {
+        open(&sk, &pk, ciphertext).is_err() && stream_open(ciphertext).is_err()
+    };
+
+    // a flipped bit in the header, reply, every chunk body and tag
+    let first = HEADER_BYTES;
+    let second = first + SEALED_CHUNK_BYTES;
+    let third = second + SEALED_CHUNK_BYTES;
+    for &i in &[0, 5, 6, 7, 8, 100, HEADER_BYTES - 1, first, second - 1, second + 9, third, ciphertext.len() - 1] {
+        let mut bad = ciphertext.clone();
+        bad[i] ^= 0x10;
+        assert!(fails(&bad), "bit flip at {}", i);
+    }
+    let mut bad = ciphertext.clone();
+    bad[6] = 2;
+    assert_eq!(open(&sk, &pk, &bad), Err(Error::UnsupportedVersion));
+
+    // truncated at a chunk boundary or mid-chunk, extended, or reordered
+    assert!(fails(&ciphertext[..third]));
+    assert!(fails(&ciphertext[..second]));
+    assert!(fails(&ciphertext[..ciphertext.len() - 1]));
+    assert!(fails(&ciphertext[..HEADER_BYTES]));
+    assert!(fails(&[&ciphertext[..], &[0]].concat()));
+    assert!(fails(&[&ciphertext[..], &ciphertext[third..]].concat()));
+    assert!(fails(&[&ciphertext[..first], &ciphertext[second..third], &ciphertext[first..second], &ciphertext[third..]].concat()));
+
+    // another message's header, or another recipient
+    let other = seal(&mut rng, Algorithm::CURRENT, &pk, &plaintext).unwrap();
+    assert!(fails(&[&other[..first], &ciphertext[first..]].concat()));
+    let (other_sk, _) = {
+        use ::params::SENDABYTES;
+        let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+        ::keygen(&mut rng, &mut sk, &mut pk);
+        (sk, pk)
+    };
+    assert_eq!(open(&other_sk, &pk, &ciphertext), Err(Error::DecryptionFailed));
+    let (_, other_pk) = {
+        use ::params::SENDABYTES;
+        let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+        ::keygen(&mut rng, &mut sk, &mut pk);
+        (sk, PublicKey(pk))
+    };
+    assert_eq!(open(&sk, &other_pk, &ciphertext), Err(Error::DecryptionFailed));
+
+    assert_eq!(stream_open(&ciphertext).unwrap(), plaintext);
+}
+
+#[test]
+fn test_envelope_sampler() {
+    use rand::{ SeedableRng, ChaChaRng };
+    use ::params::SENDABYTES;
+
+    // a message to a key made with either sampler opens in any build
+    let mut rng = ChaChaRng::from_seed(&[0x7361, 0x6d70]);
+    for &alg in Algorithm::SUPPORTED {
+        let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+        ::keygen_alg(&mut Workspace::new(), alg, &mut rng, &mut sk, &mut pk);
+        let pk = PublicKey(pk);
+        let ciphertext = seal(&mut rng, alg, &pk, b"sampler").unwrap();
+        assert_eq!(ciphertext[7], frame::sampler(alg));
+        assert_eq!(open(&sk, &pk, &ciphertext).unwrap(), b"sampler");
+    }
+
+    let (_, pk) = key_pair();
+    for &alg in &[Algorithm::NewHope1024, Algorithm::NewHope1024Tor] {
+        if !alg.is_supported() {
+            assert_eq!(seal(&mut rng, alg, &pk, b"").err(), Some(Error::UnsupportedAlgorithm));
+            assert!(Sealer::new(&mut rng, alg, &pk, Vec::new()).is_err());
+        }
+    }
+}
+
+#[test]
+fn test_envelope_reencryption() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x666f]);
+    let (sk, pk) = key_pair();
+    let ciphertext = seal(&mut rng, Algorithm::CURRENT, &pk, b"fo").unwrap();
+
+    // the header is a function of m and the recipient's key alone
+    let mut m = [0; SEED_BYTES];
+    ChaChaRng::from_seed(&[0x666f]).fill_bytes(&mut m);
+    assert_eq!(encapsulate(Algorithm::CURRENT, &pk, &m)[..], ciphertext[..HEADER_BYTES]);
+
+    // so any change to the reply, even one that leaves the shared key and
+    // hence m intact, fails at the header rather than at a chunk
+    let rec = 8 + POLY_BYTES;
+    for &i in &[8, 8 + 1000, rec, rec + 100, HEADER_BYTES - SEED_BYTES, HEADER_BYTES - 1] {
+        for &bit in &[0x01, 0x40] {
+            let mut bad = ciphertext.clone();
+            bad[i] ^= bit;
+            assert!(open_header(&sk, &pk, &bad[..HEADER_BYTES]).is_err(), "byte {}", i);
+        }
+    }
+    let mut bad = ciphertext.clone();
+    bad[rec + 7] ^= 0x11;
+    assert_eq!(open(&sk, &pk, &bad), Err(Error::DecryptionFailed));
+    assert_eq!(open(&sk, &pk, &ciphertext).unwrap(), b"fo");
+}
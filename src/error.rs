@@ -0,0 +1,59 @@
+use std::fmt;
+use std::error;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Input has the wrong length.
+    InvalidLength,
+    /// Input is not well-formed DER/PEM.
+    InvalidEncoding,
+    /// Algorithm identifier does not match this build's parameter set.
+    UnsupportedAlgorithm,
+    /// Key bytes contain a coefficient that is not reduced mod `Q`.
+    InvalidKey,
+    /// A handshake message arrived in the wrong state.
+    UnexpectedMessage,
+    /// A framed message has an unknown protocol version.
+    UnsupportedVersion,
+    /// The peers share no parameter set.
+    NoCommonAlgorithm,
+    /// The handshake has not completed.
+    Incomplete,
+    /// The peer's confirmation tag doesn't match: the keys differ or the
+    /// transcript was tampered with.
+    KeyConfirmationFailed,
+    /// The peer's signature over the transcript doesn't verify.
+    BadSignature,
+    /// A stateful signer has used up its one-time keys.
+    KeyExhausted,
+    /// The peers don't hold the same pre-shared key identifier.
+    UnknownPskIdentity,
+    /// Authenticated decryption failed.
+    DecryptionFailed,
+    /// A resumption ticket is forged, expired or sealed under another key.
+    InvalidTicket
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Error::InvalidLength => "invalid length",
+            Error::InvalidEncoding => "invalid encoding",
+            Error::UnsupportedAlgorithm => "unsupported algorithm",
+            Error::InvalidKey => "invalid key",
+            Error::UnexpectedMessage => "unexpected message",
+            Error::UnsupportedVersion => "unsupported version",
+            Error::NoCommonAlgorithm => "no common algorithm",
+            Error::Incomplete => "handshake incomplete",
+            Error::KeyConfirmationFailed => "key confirmation failed",
+            Error::BadSignature => "bad signature",
+            Error::KeyExhausted => "signing key exhausted",
+            Error::UnknownPskIdentity => "unknown PSK identity",
+            Error::DecryptionFailed => "decryption failed",
+            Error::InvalidTicket => "invalid ticket"
+        })
+    }
+}
+
+impl error::Error for Error {}
@@ -1,87 +1,267 @@
+//! Reconciliation without secret-dependent branches.
+//!
+//! Secret values are only combined with wrapping `u32` arithmetic, and masks
+//! come from logical shifts of the sign bit. The per-coefficient work lives in
+//! `helprec_kernel` and `rec_kernel`, which are kept out of line so that
+//! `test_kernels_branch_free` can check their machine code on x86_64; the
+//! surrounding loops only branch on the public index. That test needs
+//! `objdump` and checks the optimised code, so debug builds skip it. Nothing
+//! runs it automatically: run `cargo test --release` before a release.
+
 use rand::Rng;
 use ::params::Q;
 
+// Multiples of `Q` are spelled out as constants so that debug builds don't
+// put overflow checks into the kernels.
+const Q1: u32 = Q as u32;
+const Q2: u32 = 2 * Q1;
+const Q4: u32 = 4 * Q1;
+const Q8: u32 = 8 * Q1;
+const Q16: u32 = 16 * Q1;
+const QM1: u32 = Q1 - 1;
+const Q2M1: u32 = Q2 - 1;
+const Q4M1: u32 = Q4 - 1;
+
+
+/// `!0` if `x` is negative as an `i32`, `0` otherwise.
+#[inline(always)]
+fn neg_mask(x: u32) -> u32 {
+    0u32.wrapping_sub(x >> 31)
+}
 
-fn f(v0: &mut i32, v1: &mut i32, x: i32) -> i32 {
-    let mut b = x * 2730;
-    let mut t = b >> 25;
-    b = x - t * 12289;
-    b = 12288 - b;
-    b >>= 31;
-    t -= b;
+/// `|x|` of `x` read as an `i32`.
+#[inline(always)]
+fn abs(x: u32) -> u32 {
+    let m = neg_mask(x);
+    (x ^ m).wrapping_sub(m)
+}
 
-    let mut r = t & 1;
-    *v0 = (t >> 1) + r;
+/// `a` if `mask` is `0`, `b` if it is `!0`.
+#[inline(always)]
+fn select(mask: u32, a: u32, b: u32) -> u32 {
+    (!mask & a) ^ (mask & b)
+}
 
-    t -= 1;
-    r = t & 1;
-    *v1 = (t >> 1) + r;
+/// Returns `|x - 2Q v0|` and the two candidates `v0 = ceil(t / 2)`,
+/// `v1 = floor(t / 2)` for `t = floor(x / Q)`.
+#[inline(always)]
+fn f(x: u32) -> (u32, u32, u32) {
+    let mut t = x.wrapping_mul(2730) >> 25;
+    let b = x.wrapping_sub(t.wrapping_mul(Q1));
+    t = t.wrapping_add(QM1.wrapping_sub(b) >> 31);
 
-    (x - (*v0 * 2 * Q as i32)).abs()
+    let v0 = t.wrapping_add(1) >> 1;
+    let v1 = t >> 1;
+    (abs(x.wrapping_sub(v0.wrapping_mul(Q2))), v0, v1)
 }
 
-fn g(x: i32) -> i32 {
-    let mut b = x * 2730;
-    let mut t = b >> 27;
-    b = x - t * 49156;
-    b = 49155 - b;
-    b >>= 31;
-    t -= b;
+/// Distance of `x` to the nearest multiple of `8Q`.
+#[inline(always)]
+fn g(x: u32) -> u32 {
+    let mut t = x.wrapping_mul(2730) >> 27;
+    let b = x.wrapping_sub(t.wrapping_mul(Q4));
+    t = t.wrapping_add(Q4M1.wrapping_sub(b) >> 31);
 
-    let c = t & 1;
-    t = (t >> 1) + c;
+    t = t.wrapping_add(1) >> 1;
+    abs(t.wrapping_mul(Q8).wrapping_sub(x))
+}
 
-    t *= 8 * Q as i32;
+/// `16Q + 8v - Qc`.
+#[inline(always)]
+fn rec_term(v: u32, c: u32) -> u32 {
+    Q16.wrapping_add(v.wrapping_mul(8)).wrapping_sub(Q1.wrapping_mul(c))
+}
 
-    (t - x).abs()
+#[inline(always)]
+fn ld_decode(x: &[u32; 4]) -> u32 {
+    let t = g(x[0])
+        .wrapping_add(g(x[1]))
+        .wrapping_add(g(x[2]))
+        .wrapping_add(g(x[3]));
+    t.wrapping_sub(Q8) >> 31
 }
 
-fn ld_decode(xi0: i32, xi1: i32, xi2: i32, xi3: i32) -> i16 {
-    let mut t = g(xi0) + g(xi1) + g(xi2) + g(xi3);
-    t -= 8 * Q as i32;
-    t >>= 31;
 
-    t as i16 & 1
+#[inline(never)]
+fn helprec_kernel(v: &[u32; 4], rbit: u32) -> [u32; 4] {
+    let (d0, v00, v10) = f(v[0].wrapping_mul(8).wrapping_add(rbit.wrapping_mul(4)));
+    let (d1, v01, v11) = f(v[1].wrapping_mul(8).wrapping_add(rbit.wrapping_mul(4)));
+    let (d2, v02, v12) = f(v[2].wrapping_mul(8).wrapping_add(rbit.wrapping_mul(4)));
+    let (d3, v03, v13) = f(v[3].wrapping_mul(8).wrapping_add(rbit.wrapping_mul(4)));
+    let d = d0.wrapping_add(d1).wrapping_add(d2).wrapping_add(d3);
+    let k = neg_mask(Q2M1.wrapping_sub(d));
+
+    let t0 = select(k, v00, v10);
+    let t1 = select(k, v01, v11);
+    let t2 = select(k, v02, v12);
+    let t3 = select(k, v03, v13);
+
+    [
+        t0.wrapping_sub(t3) & 3,
+        t1.wrapping_sub(t3) & 3,
+        t2.wrapping_sub(t3) & 3,
+        (k & 1).wrapping_add(t3.wrapping_mul(2)) & 3
+    ]
+}
+
+#[inline(never)]
+fn rec_kernel(v: &[u32; 4], c: &[u32; 4]) -> u32 {
+    ld_decode(&[
+        rec_term(v[0], c[0].wrapping_mul(2).wrapping_add(c[3])),
+        rec_term(v[1], c[1].wrapping_mul(2).wrapping_add(c[3])),
+        rec_term(v[2], c[2].wrapping_mul(2).wrapping_add(c[3])),
+        rec_term(v[3], c[3])
+    ])
 }
 
 
 pub fn helprec<R: Rng>(c: &mut [u16], v: &[u16], rng: &mut R) {
-    let (mut v0, mut v1, mut v_tmp) = ([0; 4], [0; 4], [0; 4]);
     let mut r = [0; 32];
     rng.fill_bytes(&mut r);
 
     for i in 0..256 {
-        let rbit = ((r[i >> 3] >> (i & 7)) & 1) as i32;
-        let mut k = f(&mut v0[0], &mut v1[0], 8 * v[0 + i] as i32 + 4 * rbit)
-            + f(&mut v0[1], &mut v1[1], 8 * v[256 + i] as i32 + 4 * rbit)
-            + f(&mut v0[2], &mut v1[2], 8 * v[512 + i] as i32 + 4 * rbit)
-            + f(&mut v0[3], &mut v1[3], 8 * v[768 + i] as i32 + 4 * rbit);
-        k = (2 * Q as i32 - 1 - k) >> 31;
-
-        v_tmp[0] = (!k & v0[0]) ^ (k & v1[0]);
-        v_tmp[1] = (!k & v0[1]) ^ (k & v1[1]);
-        v_tmp[2] = (!k & v0[2]) ^ (k & v1[2]);
-        v_tmp[3] = (!k & v0[3]) ^ (k & v1[3]);
-
-        c[0 + i] = ((v_tmp[0] - v_tmp[3]) & 3) as u16;
-        c[256 + i] = ((v_tmp[1] - v_tmp[3]) & 3) as u16;
-        c[512 + i] = ((v_tmp[2] - v_tmp[3]) & 3) as u16;
-        c[768 + i] = ((-k + 2 * v_tmp[3]) & 3) as u16;
+        let rbit = ((r[i >> 3] >> (i & 7)) & 1) as u32;
+        let t = helprec_kernel(
+            &[v[i] as u32, v[256 + i] as u32, v[512 + i] as u32, v[768 + i] as u32],
+            rbit
+        );
+
+        c[i] = t[0] as u16;
+        c[256 + i] = t[1] as u16;
+        c[512 + i] = t[2] as u16;
+        c[768 + i] = t[3] as u16;
     }
 }
 
 pub fn rec(key: &mut [u8], v: &[u16], c: &[u16]) {
-    let mut tmp = [0; 4];
     for i in 0..256 {
-        tmp[0] = 16 * Q as i32 + 8 * v[0 + i] as i32
-            - Q as i32 * (2 * c[0 + i] as i32 + c[768 + i] as i32);
-        tmp[1] = 16 * Q as i32 + 8 * v[256 + i] as i32
-            - Q as i32 * (2 * c[256 + i] as i32 + c[768 + i] as i32);
-        tmp[2] = 16 * Q as i32 + 8 * v[512 + i] as i32
-            - Q as i32 * (2 * c[512 + i] as i32 + c[768 + i] as i32);
-        tmp[3] = 16 * Q as i32 + 8 * v[0 + i] as i32
-            - Q as i32 * c[768 + i] as i32;
-
-        key[i >> 3] |= (ld_decode(tmp[0], tmp[1], tmp[2], tmp[3]) << (i & 7)) as u8;
+        let bit = rec_kernel(
+            &[v[i] as u32, v[256 + i] as u32, v[512 + i] as u32, v[i] as u32],
+            &[c[i] as u32, c[256 + i] as u32, c[512 + i] as u32, c[768 + i] as u32]
+        );
+        key[i >> 3] |= (bit << (i & 7)) as u8;
+    }
+}
+
+
+#[cfg(test)]
+mod signed {
+    //! The previous implementation on `i32`, kept to check equivalence.
+
+    use ::params::Q;
+
+    pub fn f(v0: &mut i32, v1: &mut i32, x: i32) -> i32 {
+        let mut b = x * 2730;
+        let mut t = b >> 25;
+        b = x - t * 12289;
+        b = 12288 - b;
+        b >>= 31;
+        t -= b;
+
+        let mut r = t & 1;
+        *v0 = (t >> 1) + r;
+
+        t -= 1;
+        r = t & 1;
+        *v1 = (t >> 1) + r;
+
+        (x - (*v0 * 2 * Q as i32)).abs()
+    }
+
+    pub fn g(x: i32) -> i32 {
+        let mut b = x * 2730;
+        let mut t = b >> 27;
+        b = x - t * 49156;
+        b = 49155 - b;
+        b >>= 31;
+        t -= b;
+
+        let c = t & 1;
+        t = (t >> 1) + c;
+
+        t *= 8 * Q as i32;
+
+        (t - x).abs()
+    }
+}
+
+#[test]
+fn test_f_g() {
+    for x in 0..(8 * 16384 + 4) {
+        let (mut v0, mut v1) = (0, 0);
+        let d = signed::f(&mut v0, &mut v1, x);
+        assert_eq!(f(x as u32), (d as u32, v0 as u32, v1 as u32), "f({})", x);
+    }
+
+    for x in (7 * Q as i32)..(24 * Q as i32 + 8 * 16384) {
+        assert_eq!(g(x as u32), signed::g(x) as u32, "g({})", x);
+    }
+}
+
+#[test]
+fn test_helprec_rec() {
+    use rand::{ SeedableRng, ChaChaRng };
+    use ::params::N;
+
+    let mut rng = ChaChaRng::from_seed(&[0x7265, 0x6363, 0x7465, 0x7374]);
+    let (mut v, mut c) = ([0; N], [0; N]);
+
+    for _ in 0..64 {
+        for x in v.iter_mut() {
+            *x = rng.gen_range(0, Q as u16);
+        }
+        helprec(&mut c, &v, &mut rng);
+        assert!(c.iter().all(|&x| x < 4));
+
+        let (mut key, mut key2) = ([0; 32], [0; 32]);
+        rec(&mut key, &v, &c);
+
+        // a small perturbation of v decodes to the same key
+        for x in v.iter_mut() {
+            *x = (*x + Q as u16 + rng.gen_range(0, 9) - 4) % Q as u16;
+        }
+        rec(&mut key2, &v, &c);
+        assert_eq!(key, key2);
+    }
+}
+
+/// Disassemble this test binary and check that the reconciliation kernels
+/// contain no conditional branches, calls or divisions.
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+#[test]
+#[cfg_attr(debug_assertions, ignore)]
+fn test_kernels_branch_free() {
+    use std::env;
+    use std::process::Command;
+
+    let output = Command::new("objdump")
+        .args(["-d", "-C", "--no-show-raw-insn"])
+        .arg(env::current_exe().unwrap())
+        .output()
+        .expect("objdump is required for test_kernels_branch_free");
+    assert!(output.status.success(), "objdump failed: {}", String::from_utf8_lossy(&output.stderr));
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    for name in &["error_correction::helprec_kernel", "error_correction::rec_kernel"] {
+        let mut found = false;
+        let mut lines = output.lines();
+
+        while let Some(line) = lines.next() {
+            if !(line.ends_with(">:") && line.contains(name)) {
+                continue;
+            }
+            found = true;
+
+            for insn in lines.by_ref().take_while(|line| !line.trim().is_empty()) {
+                let mnemonic = insn.split('\t').nth(1).unwrap_or("").split_whitespace().next().unwrap_or("");
+                let forbidden = (mnemonic.starts_with('j') && mnemonic != "jmp")
+                    || mnemonic.starts_with("loop")
+                    || mnemonic.starts_with("call")
+                    || mnemonic.contains("div");
+                assert!(!forbidden, "{}: {}", name, insn);
+            }
+        }
+
+        assert!(found, "{} not found in the disassembly", name);
     }
 }
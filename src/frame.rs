@@ -0,0 +1,335 @@
+//! Versioned framing for handshake messages.
+//!
+//! A bare offer or reply doesn't say which sampler produced `a`, so peers
+//! built with and without `tor` would derive different keys without
+//! noticing. Framed messages carry a protocol version, the parameter set and,
+//! for each public key, the sampler:
+//!
+//! ```text
+//! offer:        version | 1 | params | count | count * (sampler | SENDABYTES)
+//!               [ | id length | PSK id ]
+//! reply:        version | 2 | params | sampler | SENDBBYTES
+//! confirmation: version | 3 | params | 32-byte tag
+//! signature:    version | 4 | params | signature
+//! ticket:       version | 5 | params | lifetime | ticket
+//! resume:       version | 6 | params | 32-byte nonce | ticket
+//! resumed:      version | 7 | params | 32-byte nonce
+//! ```
+//!
+//! Every field before the keys is one byte. `params` is 1 for N = 1024,
+//! Q = 12289; `sampler` is 0 for the reference sampler and 1 for Tor's.
+//! The initiator sends one public key per parameter set it is willing to
+//! use, in order of preference, and the responder answers exactly one. An
+//! offer that asks for a pre-shared key names it by an identifier of 1 to 255
+//! bytes after the keys. `lifetime` is four bytes, big-endian, in seconds;
+//! a ticket is opaque to the initiator (see `ticket`).
+
+use byteorder::{ ByteOrder, BigEndian };
+use ::params::{ SENDABYTES, SENDBBYTES, Algorithm };
+use ::message::{ PublicKey, Reply };
+use ::error::Error;
+
+
+pub const VERSION: u8 = 1;
+
+const TYPE_OFFER: u8 = 1;
+const TYPE_REPLY: u8 = 2;
+const TYPE_CONFIRMATION: u8 = 3;
+const TYPE_SIGNATURE: u8 = 4;
+const TYPE_TICKET: u8 = 5;
+const TYPE_RESUME: u8 = 6;
+const TYPE_RESUMED: u8 = 7;
+const PARAMS_1024: u8 = 1;
+const SAMPLER_REF: u8 = 0;
+const SAMPLER_TOR: u8 = 1;
+
+pub(crate) fn sampler(alg: Algorithm) -> u8 {
+    match alg {
+        Algorithm::NewHope1024 => SAMPLER_REF,
+        Algorithm::NewHope1024Tor => SAMPLER_TOR
+    }
+}
+
+pub(crate) fn from_sampler(id: u8) -> Option<Algorithm> {
+    match id {
+        SAMPLER_REF => Some(Algorithm::NewHope1024),
+        SAMPLER_TOR => Some(Algorithm::NewHope1024Tor),
+        _ => None
+    }
+}
+
+fn check_header(bytes: &[u8], ty: u8) -> Result<(), Error> {
+    if bytes.len() < 3 {
+        return Err(Error::InvalidLength);
+    }
+    if bytes[0] != VERSION {
+        return Err(Error::UnsupportedVersion);
+    }
+    if bytes[1] != ty {
+        return Err(Error::UnexpectedMessage);
+    }
+    if bytes[2] != PARAMS_1024 {
+        return Err(Error::UnsupportedAlgorithm);
+    }
+    Ok(())
+}
+
+
+/// Public keys of an offer and their parameter sets.
+pub type Shares = Vec<(Algorithm, PublicKey)>;
+
+/// Frame one public key per parameter set, most preferred first, and the
+/// identifier of the pre-shared key to mix in, if any.
+pub fn encode_offer(shares: &[(Algorithm, PublicKey)], psk_id: Option<&[u8]>) -> Vec<u8> {
+    assert!(!shares.is_empty() && shares.len() < 256);
+
+    let mut out = Vec::with_capacity(4 + shares.len() * (1 + SENDABYTES) + 256);
+    out.extend_from_slice(&[VERSION, TYPE_OFFER, PARAMS_1024, shares.len() as u8]);
+    for &(alg, ref pk) in shares {
+        out.push(sampler(alg));
+        out.extend_from_slice(&pk.0);
+    }
+    if let Some(id) = psk_id {
+        assert!(!id.is_empty() && id.len() < 256);
+        out.push(id.len() as u8);
+        out.extend_from_slice(id);
+    }
+    out
+}
+
+/// Parse a framed offer, skipping public keys for unknown samplers.
+pub fn decode_offer(bytes: &[u8]) -> Result<(Shares, Option<&[u8]>), Error> {
+    check_header(bytes, TYPE_OFFER)?;
+    let count = *bytes.get(3).ok_or(Error::InvalidLength)? as usize;
+    let end = 4 + count * (1 + SENDABYTES);
+    let psk_id = match bytes.len() {
+        len if len == end => None,
+        len if len > end + 1 && len == end + 1 + bytes[end] as usize => Some(&bytes[end + 1..]),
+        _ => return Err(Error::InvalidLength)
+    };
+    if count == 0 {
+        return Err(Error::InvalidEncoding);
+    }
+
+    let mut seen = [false; 256];
+    let mut shares = Vec::with_capacity(count);
+    for share in bytes[4..end].chunks(1 + SENDABYTES) {
+        if seen[share[0] as usize] {
+            return Err(Error::InvalidEncoding);
+        }
+        seen[share[0] as usize] = true;
+
+        if let Some(alg) = from_sampler(share[0]) {
+            let pk = PublicKey::from_bytes(&share[1..]).ok_or(Error::InvalidKey)?;
+            shares.push((alg, pk));
+        }
+    }
+    Ok((shares, psk_id))
+}
+
+pub fn encode_reply(alg: Algorithm, msg: &Reply) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + SENDBBYTES);
+    out.extend_from_slice(&[VERSION, TYPE_REPLY, PARAMS_1024, sampler(alg)]);
+    out.extend_from_slice(&msg.0);
+    out
+}
+
+pub fn decode_reply(bytes: &[u8]) -> Result<(Algorithm, Reply), Error> {
+    check_header(bytes, TYPE_REPLY)?;
+    if bytes.len() != 4 + SENDBBYTES {
+        return Err(Error::InvalidLength);
+    }
+    let alg = from_sampler(bytes[3]).ok_or(Error::UnsupportedAlgorithm)?;
+    let msg = Reply::from_bytes(&bytes[4..]).ok_or(Error::InvalidKey)?;
+    Ok((alg, msg))
+}
+
+pub fn encode_confirmation(tag: &[u8; 32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(3 + 32);
+    out.extend_from_slice(&[VERSION, TYPE_CONFIRMATION, PARAMS_1024]);
+    out.extend_from_slice(tag);
+    out
+}
+
+pub fn decode_confirmation(bytes: &[u8]) -> Result<[u8; 32], Error> {
+    check_header(bytes, TYPE_CONFIRMATION)?;
+    if bytes.len() != 3 + 32 {
+        return Err(Error::InvalidLength);
+    }
+    let mut tag = [0; 32];
+    tag.copy_from_slice(&bytes[3..]);
+    Ok(tag)
+}
+
+pub fn encode_signature(signature: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(3 + signature.len());
+    out.extend_from_slice(&[VERSION, TYPE_SIGNATURE, PARAMS_1024]);
+    out.extend_from_slice(signature);
+    out
+}
+
+pub fn decode_signature(bytes: &[u8]) -> Result<&[u8], Error> {
+    check_header(bytes, TYPE_SIGNATURE)?;
+    Ok(&bytes[3..])
+}
+
+pub fn encode_ticket(lifetime: u32, ticket: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(7 + ticket.len());
+    out.extend_from_slice(&[VERSION, TYPE_TICKET, PARAMS_1024, 0, 0, 0, 0]);
+    BigEndian::write_u32(&mut out[3..7], lifetime);
+    out.extend_from_slice(ticket);
+    out
+}
+
+pub fn decode_ticket(bytes: &[u8]) -> Result<(u32, &[u8]), Error> {
+    check_header(bytes, TYPE_TICKET)?;
+    if bytes.len() <= 7 {
+        return Err(Error::InvalidLength);
+    }
+    Ok((BigEndian::read_u32(&bytes[3..7]), &bytes[7..]))
+}
+
+pub fn encode_resume(nonce: &[u8; 32], ticket: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(3 + 32 + ticket.len());
+    out.extend_from_slice(&[VERSION, TYPE_RESUME, PARAMS_1024]);
+    out.extend_from_slice(nonce);
+    out.extend_from_slice(ticket);
+    out
+}
+
+pub fn decode_resume(bytes: &[u8]) -> Result<([u8; 32], &[u8]), Error> {
+    check_header(bytes, TYPE_RESUME)?;
+    if bytes.len() <= 3 + 32 {
+        return Err(Error::InvalidLength);
+    }
+    let mut nonce = [0; 32];
+    nonce.copy_from_slice(&bytes[3..35]);
+    Ok((nonce, &bytes[35..]))
+}
+
+pub fn encode_resumed(nonce: &[u8; 32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(3 + 32);
+    out.extend_from_slice(&[VERSION, TYPE_RESUMED, PARAMS_1024]);
+    out.extend_from_slice(nonce);
+    out
+}
+
+pub fn decode_resumed(bytes: &[u8]) -> Result<[u8; 32], Error> {
+    check_header(bytes, TYPE_RESUMED)?;
+    if bytes.len() != 3 + 32 {
+        return Err(Error::InvalidLength);
+    }
+    let mut nonce = [0; 32];
+    nonce.copy_from_slice(&bytes[3..]);
+    Ok(nonce)
+}
+
+/// The first of `accepted` that was `offered`.
+pub fn select(offered: &[Algorithm], accepted: &[Algorithm]) -> Result<Algorithm, Error> {
+    accepted.iter()
+        .find(|alg| offered.contains(alg))
+        .cloned()
+        .ok_or(Error::NoCommonAlgorithm)
+}
+
+
+#[test]
+fn test_offer() {
+    use rand::{ SeedableRng, ChaChaRng };
+    use ::params::POLY_BYTES;
+
+    let mut rng = ChaChaRng::from_seed(&[0x6672, 0x616d, 0x65]);
+    let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+    ::keygen(&mut rng, &mut sk, &mut pk);
+    let pk = PublicKey(pk);
+
+    let shares = [(Algorithm::NewHope1024Tor, pk.clone()), (Algorithm::NewHope1024, pk.clone())];
+    let bytes = encode_offer(&shares, None);
+    assert_eq!(bytes.len(), 4 + 2 * (1 + SENDABYTES));
+    assert_eq!(&bytes[..5], &[1, 1, 1, 2, 1]);
+    assert_eq!(decode_offer(&bytes).unwrap(), (shares.to_vec(), None));
+
+    // unknown samplers are skipped, repeated ones rejected
+    let mut other = bytes.clone();
+    other[4] = 7;
+    assert_eq!(decode_offer(&other).unwrap().0, shares[1..].to_vec());
+    other[4] = SAMPLER_REF;
+    assert_eq!(decode_offer(&other), Err(Error::InvalidEncoding));
+
+    let mut other = bytes.clone();
+    other[0] = 2;
+    assert_eq!(decode_offer(&other), Err(Error::UnsupportedVersion));
+    let mut other = bytes.clone();
+    other[2] = 0;
+    assert_eq!(decode_offer(&other), Err(Error::UnsupportedAlgorithm));
+    let mut other = bytes.clone();
+    other[5..12].copy_from_slice(&[0xff; 7]);
+    assert_eq!(decode_offer(&other), Err(Error::InvalidKey));
+
+    assert_eq!(decode_offer(&bytes[..bytes.len() - 1]), Err(Error::InvalidLength));
+    assert_eq!(decode_offer(&[VERSION, TYPE_OFFER, PARAMS_1024, 0]), Err(Error::InvalidEncoding));
+    assert_eq!(decode_reply(&bytes), Err(Error::UnexpectedMessage));
+
+    let bytes = encode_offer(&shares[1..], Some(b"link 7"));
+    assert_eq!(&bytes[bytes.len() - 7..], b"\x06link 7");
+    assert_eq!(decode_offer(&bytes).unwrap(), (shares[1..].to_vec(), Some(&b"link 7"[..])));
+    assert_eq!(decode_offer(&bytes[..bytes.len() - 1]), Err(Error::InvalidLength));
+    assert_eq!(decode_offer(&bytes[..bytes.len() - 6]), Err(Error::InvalidLength));
+    let mut other = bytes.clone();
+    other.push(0);
+    assert_eq!(decode_offer(&other), Err(Error::InvalidLength));
+}
+
+#[test]
+fn test_reply() {
+    let msg = Reply([0; SENDBBYTES]);
+    let bytes = encode_reply(Algorithm::NewHope1024, &msg);
+    assert_eq!(&bytes[..4], &[1, 2, 1, 0]);
+    assert_eq!(decode_reply(&bytes), Ok((Algorithm::NewHope1024, msg)));
+
+    let mut other = bytes.clone();
+    other[3] = 7;
+    assert_eq!(decode_reply(&other), Err(Error::UnsupportedAlgorithm));
+    assert_eq!(decode_reply(&bytes[..3]), Err(Error::InvalidLength));
+    assert_eq!(decode_offer(&bytes), Err(Error::UnexpectedMessage));
+}
+
+#[test]
+fn test_confirmation() {
+    let bytes = encode_confirmation(&[5; 32]);
+    assert_eq!(&bytes[..3], &[1, 3, 1]);
+    assert_eq!(decode_confirmation(&bytes), Ok([5; 32]));
+    assert_eq!(decode_confirmation(&bytes[..34]), Err(Error::InvalidLength));
+    assert_eq!(decode_reply(&bytes), Err(Error::UnexpectedMessage));
+
+    let bytes = encode_signature(b"signature");
+    assert_eq!(decode_signature(&bytes), Ok(&b"signature"[..]));
+    assert_eq!(decode_confirmation(&bytes), Err(Error::UnexpectedMessage));
+}
+
+#[test]
+fn test_resume() {
+    let bytes = encode_ticket(3600, b"opaque");
+    assert_eq!(&bytes[..7], &[1, 5, 1, 0, 0, 0x0e, 0x10]);
+    assert_eq!(decode_ticket(&bytes), Ok((3600, &b"opaque"[..])));
+    assert_eq!(decode_ticket(&bytes[..7]), Err(Error::InvalidLength));
+
+    let bytes = encode_resume(&[4; 32], b"opaque");
+    assert_eq!(decode_resume(&bytes), Ok(([4; 32], &b"opaque"[..])));
+    assert_eq!(decode_resume(&bytes[..35]), Err(Error::InvalidLength));
+    assert_eq!(decode_offer(&bytes), Err(Error::UnexpectedMessage));
+
+    let bytes = encode_resumed(&[6; 32]);
+    assert_eq!(decode_resumed(&bytes), Ok([6; 32]));
+    assert_eq!(decode_resumed(&bytes[..34]), Err(Error::InvalidLength));
+    assert_eq!(decode_reply(&bytes), Err(Error::UnexpectedMessage));
+}
+
+#[test]
+fn test_select() {
+    use ::params::Algorithm::*;
+
+    assert_eq!(select(&[NewHope1024Tor, NewHope1024], &[NewHope1024]), Ok(NewHope1024));
+    assert_eq!(select(&[NewHope1024, NewHope1024Tor], &[NewHope1024Tor, NewHope1024]), Ok(NewHope1024Tor));
+    assert_eq!(select(&[NewHope1024Tor], &[NewHope1024]), Err(Error::NoCommonAlgorithm));
+}
@@ -0,0 +1,1108 @@
+//! Sans-IO handshake state machines.
+//!
+//! `Initiator` and `Responder` wrap `keygen`, `sharedb` and `shareda`: they
+//! produce and consume framed message bytes (see `frame`), negotiate the
+//! parameter set, keep a running hash of every message exchanged, and refuse
+//! messages that arrive in the wrong state. The session key is bound to both
+//! messages and the application context by `kdf::session_key`. Moving the
+//! bytes between the two parties is left to the caller.
+//!
+//! With `with_confirmation`, a party holds back its key until the peer's
+//! confirmation message, a MAC over the transcript hash, has been checked;
+//! a failed reconciliation or a tampered message then surfaces as
+//! `Error::KeyConfirmationFailed` rather than as garbled application data.
+//!
+//! With `with_psk`, both parties also mix a pre-shared key into the session
+//! key (see `kdf::psk_session_key`), so the key stays secret if either the
+//! pre-shared key or the lattice problem does. The offer names the key by its
+//! identifier; a responder holding a different one, or none, fails with
+//! `Error::UnknownPskIdentity`. Pair it with `with_confirmation` to detect a
+//! wrong key.
+//!
+//! Once complete, the responder can issue a resumption ticket with `ticket`,
+//! which the initiator accepts with `read_ticket` and later passes to
+//! `Initiator::resume`; see the `ticket` module.
+//!
+//! With `with_verifier`, a party also holds back its key until the peer has
+//! signed the transcript hash with its long-term key (see `auth`); signatures
+//! are checked before confirmation messages. Either or both parties may
+//! require one.
+//!
+//! ```
+//! # extern crate rand;
+//! # extern crate newhope;
+//! # use rand::{ Rng, OsRng, ChaChaRng };
+//! use newhope::handshake::{ Initiator, Responder, State };
+//!
+//! # fn main() {
+//! # let mut rng = OsRng::new().unwrap().gen::<ChaChaRng>();
+//! let (mut alice, mut bob) = (Initiator::new(), Responder::new());
+//!
+//! let offer = alice.offer(&mut rng).unwrap();
+//! let reply = bob.read_offer(&mut rng, &offer).unwrap();
+//! alice.read_reply(&reply).unwrap();
+//!
+//! assert_eq!(alice.state(), State::Complete);
+//! assert_eq!(alice.algorithm(), bob.algorithm());
+//! assert_eq!(alice.key(), bob.key());
+//! assert_eq!(alice.transcript_hash(), bob.transcript_hash());
+//! # }
+//! ```
+
+use rand::Rng;
+use tiny_keccak::Keccak;
+use ::params::{ POLY_BYTES, SENDABYTES, SENDBBYTES, Algorithm };
+use ::message::{ PublicKey, Reply };
+use ::error::Error;
+use ::workspace::Workspace;
+use ::auth::{ Signer, Verifier };
+use ::ticket::{ self, Ticket, TicketKey };
+use ::{ frame, kdf };
+
+
+const TRANSCRIPT_LABEL: &[u8] = b"newhope handshake";
+const SIGNATURE_LABEL: &[u8] = b"newhope handshake signature v1";
+
+/// Where a party is in the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// No message has been sent or received.
+    Start,
+    /// The initiator has sent its offer.
+    AwaitingReply,
+    /// The key is derived but held back until the peer's signature has been
+    /// checked.
+    AwaitingSignature,
+    /// The key is derived but held back until the peer's confirmation
+    /// message has been checked.
+    AwaitingConfirmation,
+    /// The shared key is available.
+    Complete,
+    /// A message was rejected; the handshake cannot continue.
+    Failed
+}
+
+const INITIATOR: &[u8] = b"initiator";
+const RESPONDER: &[u8] = b"responder";
+
+fn confirmation(key: &[u8; 32], role: &[u8], transcript: &Transcript) -> Vec<u8> {
+    let mut tag = [0; 32];
+    kdf::confirmation_tag(key, role, &transcript.hash(), &mut tag);
+    frame::encode_confirmation(&tag)
+}
+
+fn check_confirmation(key: &[u8; 32], role: &[u8], transcript: &Transcript, msg: &[u8]) -> Result<(), Error> {
+    let tag = frame::decode_confirmation(msg)?;
+    let mut expected = [0; 32];
+    kdf::confirmation_tag(key, role, &transcript.hash(), &mut expected);
+    if kdf::ct_eq(&tag, &expected) {
+        Ok(())
+    } else {
+        Err(Error::KeyConfirmationFailed)
+    }
+}
+
+/// What a party signs: the label, its role and the transcript hash.
+fn signed_message(role: &[u8], transcript: &Transcript) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(SIGNATURE_LABEL.len() + role.len() + 32);
+    msg.extend_from_slice(SIGNATURE_LABEL);
+    msg.extend_from_slice(role);
+    msg.extend_from_slice(&transcript.hash());
+    msg
+}
+
+fn signature<S: Signer + ?Sized>(signer: &mut S, role: &[u8], transcript: &Transcript) -> Result<Vec<u8>, Error> {
+    let signature = signer.sign(&signed_message(role, transcript))?;
+    Ok(frame::encode_signature(&signature))
+}
+
+fn check_signature(verifier: &dyn Verifier, role: &[u8], transcript: &Transcript, msg: &[u8]) -> Result<(), Error> {
+    let signature = frame::decode_signature(msg)?;
+    if verifier.verify(&signed_message(role, transcript), signature) {
+        Ok(())
+    } else {
+        Err(Error::BadSignature)
+    }
+}
+
+/// A pre-shared key and its identifier; the key is zeroed on drop.
+struct Psk {
+    id: Vec<u8>,
+    key: Vec<u8>
+}
+
+impl Psk {
+    fn new(id: &[u8], key: &[u8]) -> Result<Psk, Error> {
+        if id.is_empty() || id.len() > 255 || key.is_empty() {
+            return Err(Error::InvalidLength);
+        }
+        Ok(Psk { id: id.to_vec(), key: key.to_vec() })
+    }
+}
+
+impl Drop for Psk {
+    fn drop(&mut self) {
+        for b in &mut self.key {
+            *b = 0;
+        }
+    }
+}
+
+fn session_key(key: &[u8; 32], psk: Option<&Psk>, offer: &[u8], reply: &[u8], context: &[u8], output: &mut [u8; 32]) {
+    match psk {
+        Some(psk) => kdf::psk_session_key(key, &psk.key, offer, reply, context, output),
+        None => kdf::session_key(key, offer, reply, context, output)
+    }
+}
+
+/// SHA3-256 over every message, in order.
+#[derive(Clone)]
+struct Transcript(Keccak);
+
+impl Transcript {
+    fn new() -> Transcript {
+        let mut sha3 = Keccak::new_sha3_256();
+        sha3.update(TRANSCRIPT_LABEL);
+        Transcript(sha3)
+    }
+
+    fn update(&mut self, msg: &[u8]) {
+        self.0.update(msg);
+    }
+
+    fn hash(&self) -> [u8; 32] {
+        let mut hash = [0; 32];
+        self.0.clone().finalize(&mut hash);
+        hash
+    }
+}
+
+
+/// The party that sends the offer.
+pub struct Initiator {
+    state: State,
+    algorithms: Vec<Algorithm>,
+    context: Vec<u8>,
+    confirm: bool,
+    verifier: Option<Box<dyn Verifier + Send>>,
+    psk: Option<Psk>,
+    ticket: Option<Ticket>,
+    sks: Vec<[u8; POLY_BYTES]>,
+    offer: Vec<u8>,
+    algorithm: Option<Algorithm>,
+    key: [u8; 32],
+    transcript: Transcript
+}
+
+impl Initiator {
+    /// Offer every parameter set this build supports.
+    pub fn new() -> Initiator {
+        Initiator {
+            state: State::Start,
+            algorithms: Algorithm::SUPPORTED.to_vec(),
+            context: Vec::new(),
+            confirm: false,
+            verifier: None,
+            psk: None,
+            ticket: None,
+            sks: Vec::new(),
+            offer: Vec::new(),
+            algorithm: None,
+            key: [0; 32],
+            transcript: Transcript::new()
+        }
+    }
+
+    /// Resume the session that issued `ticket` instead of running the
+    /// lattice exchange. A responder that can't open the ticket fails with
+    /// `Error::InvalidTicket`; start a full handshake then.
+    pub fn resume(ticket: &Ticket) -> Initiator {
+        let mut initiator = Initiator::new();
+        initiator.algorithms = vec![ticket.algorithm];
+        initiator.ticket = Some(ticket.clone());
+        initiator
+    }
+
+    /// Offer `algorithms`, most preferred first.
+    pub fn with_algorithms(algorithms: &[Algorithm]) -> Result<Initiator, Error> {
+        if algorithms.is_empty() {
+            return Err(Error::NoCommonAlgorithm);
+        }
+        if !algorithms.iter().all(Algorithm::is_supported) {
+            return Err(Error::UnsupportedAlgorithm);
+        }
+
+        let mut initiator = Initiator::new();
+        initiator.algorithms.clear();
+        for &alg in algorithms {
+            if !initiator.algorithms.contains(&alg) {
+                initiator.algorithms.push(alg);
+            }
+        }
+        Ok(initiator)
+    }
+
+    /// Bind the session key to `context`, which the responder must match.
+    pub fn with_context(mut self, context: &[u8]) -> Initiator {
+        self.context = context.to_vec();
+        self
+    }
+
+    /// Hold back the key until the responder's confirmation message checks out.
+    pub fn with_confirmation(mut self) -> Initiator {
+        self.confirm = true;
+        self
+    }
+
+    /// Hold back the key until the responder has signed the transcript with the
+    /// long-term key behind `verifier`.
+    pub fn with_verifier<V: Verifier + Send + 'static>(mut self, verifier: V) -> Initiator {
+        self.verifier = Some(Box::new(verifier));
+        self
+    }
+
+    /// Mix the pre-shared key `psk`, known to the responder as `id`, into the
+    /// session key. `id` must be 1 to 255 bytes and `psk` non-empty; it
+    /// should be 32 uniformly random bytes.
+    pub fn with_psk(mut self, id: &[u8], psk: &[u8]) -> Result<Initiator, Error> {
+        self.psk = Some(Psk::new(id, psk)?);
+        Ok(self)
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Generate a key pair per offered parameter set and return the framed
+    /// offer to send; when resuming, present the ticket instead.
+    pub fn offer<R: Rng>(&mut self, mut rng: R) -> Result<Vec<u8>, Error> {
+        if self.state != State::Start {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        if let Some(ref ticket) = self.ticket {
+            let mut nonce = [0; 32];
+            rng.fill_bytes(&mut nonce);
+            self.offer = frame::encode_resume(&nonce, &ticket.opaque);
+            self.transcript.update(&self.offer);
+            self.state = State::AwaitingReply;
+            return Ok(self.offer.clone());
+        }
+
+        let mut ws = Workspace::new();
+        let mut shares = Vec::with_capacity(self.algorithms.len());
+        for &alg in &self.algorithms {
+            let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+            ::keygen_alg(&mut ws, alg, &mut rng, &mut sk, &mut pk);
+            self.sks.push(sk);
+            shares.push((alg, PublicKey(pk)));
+        }
+
+        let offer = frame::encode_offer(&shares, self.psk.as_ref().map(|psk| &psk.id[..]));
+        self.transcript.update(&offer);
+        self.offer = offer.clone();
+        self.state = State::AwaitingReply;
+        Ok(offer)
+    }
+
+    /// Consume the responder's framed reply and derive the shared key.
+    ///
+    /// A malformed reply, or one for a parameter set that wasn't offered,
+    /// fails the handshake.
+    pub fn read_reply(&mut self, msg: &[u8]) -> Result<(), Error> {
+        if self.state != State::AwaitingReply {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        let result = if self.ticket.is_some() { self.read_resumed(msg) } else { self.read_share(msg) };
+
+        for sk in &mut self.sks {
+            *sk = [0; POLY_BYTES];
+        }
+        self.sks.clear();
+
+        match result {
+            Ok(alg) => {
+                self.transcript.update(msg);
+                self.algorithm = Some(alg);
+                self.state = self.held_back(false);
+                Ok(())
+            },
+            Err(err) => {
+                self.state = State::Failed;
+                Err(err)
+            }
+        }
+    }
+
+    fn read_resumed(&mut self, msg: &[u8]) -> Result<Algorithm, Error> {
+        frame::decode_resumed(msg)?;
+        let ticket = self.ticket.as_ref().unwrap();
+        kdf::session_key(&ticket.secret, &self.offer, msg, &self.context, &mut self.key);
+        Ok(ticket.algorithm)
+    }
+
+    fn read_share(&mut self, msg: &[u8]) -> Result<Algorithm, Error> {
+        frame::decode_reply(msg).and_then(|(alg, reply)| {
+            let i = self.algorithms.iter()
+                .position(|&x| x == alg)
+                .ok_or(Error::UnsupportedAlgorithm)?;
+            let mut key = [0; 32];
+            ::shareda(&mut key, &self.sks[i], &reply.0);
+            session_key(&key, self.psk.as_ref(), &self.offer, msg, &self.context, &mut self.key);
+            Ok(alg)
+        })
+    }
+
+    /// The negotiated parameter set, once the handshake is complete.
+    pub fn algorithm(&self) -> Option<Algorithm> {
+        self.algorithm
+    }
+
+    /// The session key, once the handshake is complete.
+    pub fn key(&self) -> Option<&[u8; 32]> {
+        if self.state == State::Complete { Some(&self.key) } else { None }
+    }
+
+    /// Derive `len` bytes of key material for `label` and `context`; see
+    /// `kdf::export`.
+    pub fn export(&self, label: &[u8], context: &[u8], len: usize) -> Result<Vec<u8>, Error> {
+        let key = self.key().ok_or(Error::Incomplete)?;
+        let mut output = vec![0; len];
+        kdf::export(key, label, context, &mut output);
+        Ok(output)
+    }
+
+    /// Accept a resumption ticket from the responder; `now` is the current
+    /// time in seconds since the Unix epoch.
+    pub fn read_ticket(&self, msg: &[u8], now: u64) -> Result<Ticket, Error> {
+        let key = self.key().ok_or(Error::Incomplete)?;
+        let (lifetime, opaque) = frame::decode_ticket(msg)?;
+        let mut ticket = Ticket {
+            opaque: opaque.to_vec(),
+            secret: [0; 32],
+            algorithm: self.algorithm.ok_or(Error::Incomplete)?,
+            expires: now.saturating_add(lifetime as u64)
+        };
+        kdf::resumption_secret(key, &self.transcript.hash(), &mut ticket.secret);
+        Ok(ticket)
+    }
+
+    /// The state once the key is derived, or once the peer's signature has
+    /// been checked.
+    fn held_back(&self, signed: bool) -> State {
+        if self.verifier.is_some() && !signed {
+            State::AwaitingSignature
+        } else if self.confirm {
+            State::AwaitingConfirmation
+        } else {
+            State::Complete
+        }
+    }
+
+    /// Sign the transcript for the responder, once the key is derived.
+    pub fn signature<S: Signer + ?Sized>(&self, signer: &mut S) -> Result<Vec<u8>, Error> {
+        match self.state {
+            State::AwaitingSignature | State::AwaitingConfirmation | State::Complete =>
+                signature(signer, INITIATOR, &self.transcript),
+            _ => Err(Error::UnexpectedMessage)
+        }
+    }
+
+    /// Check the responder's signature.
+    ///
+    /// A bad signature fails the handshake.
+    pub fn read_signature(&mut self, msg: &[u8]) -> Result<(), Error> {
+        if self.state != State::AwaitingSignature {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        let result = match self.verifier {
+            Some(ref verifier) => check_signature(&**verifier, RESPONDER, &self.transcript, msg),
+            None => Err(Error::UnexpectedMessage)
+        };
+        match result {
+            Ok(()) => {
+                self.state = self.held_back(true);
+                Ok(())
+            },
+            Err(err) => {
+                self.state = State::Failed;
+                Err(err)
+            }
+        }
+    }
+
+    /// The confirmation message for the responder, once the key is derived.
+    pub fn confirmation(&self) -> Result<Vec<u8>, Error> {
+        match self.state {
+            State::AwaitingSignature | State::AwaitingConfirmation | State::Complete =>
+                Ok(confirmation(&self.key, INITIATOR, &self.transcript)),
+            _ => Err(Error::UnexpectedMessage)
+        }
+    }
+
+    /// Check the responder's confirmation message and release the key.
+    ///
+    /// A mismatch fails the handshake.
+    pub fn read_confirmation(&mut self, msg: &[u8]) -> Result<(), Error> {
+        if self.state != State::AwaitingConfirmation {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        match check_confirmation(&self.key, RESPONDER, &self.transcript, msg) {
+            Ok(()) => {
+                self.state = State::Complete;
+                Ok(())
+            },
+            Err(err) => {
+                self.state = State::Failed;
+                Err(err)
+            }
+        }
+    }
+
+    /// Hash of the messages exchanged so far.
+    pub fn transcript_hash(&self) -> [u8; 32] {
+        self.transcript.hash()
+    }
+}
+
+impl Default for Initiator {
+    fn default() -> Initiator {
+        Initiator::new()
+    }
+}
+
+
+/// The party that answers the offer.
+pub struct Responder {
+    state: State,
+    algorithms: Vec<Algorithm>,
+    context: Vec<u8>,
+    confirm: bool,
+    verifier: Option<Box<dyn Verifier + Send>>,
+    psk: Option<Psk>,
+    tickets: Option<(TicketKey, u64)>,
+    algorithm: Option<Algorithm>,
+    key: [u8; 32],
+    transcript: Transcript
+}
+
+impl Responder {
+    /// Accept every parameter set this build supports.
+    pub fn new() -> Responder {
+        Responder {
+            state: State::Start,
+            algorithms: Algorithm::SUPPORTED.to_vec(),
+            context: Vec::new(),
+            confirm: false,
+            verifier: None,
+            psk: None,
+            tickets: None,
+            algorithm: None,
+            key: [0; 32],
+            transcript: Transcript::new()
+        }
+    }
+
+    /// Accept only `algorithms`, most preferred first.
+    pub fn with_algorithms(algorithms: &[Algorithm]) -> Result<Responder, Error> {
+        if algorithms.is_empty() {
+            return Err(Error::NoCommonAlgorithm);
+        }
+        if !algorithms.iter().all(Algorithm::is_supported) {
+            return Err(Error::UnsupportedAlgorithm);
+        }
+
+        let mut responder = Responder::new();
+        responder.algorithms = algorithms.to_vec();
+        Ok(responder)
+    }
+
+    /// Bind the session key to `context`, which the initiator must match.
+    pub fn with_context(mut self, context: &[u8]) -> Responder {
+        self.context = context.to_vec();
+        self
+    }
+
+    /// Hold back the key until the initiator's confirmation message checks out.
+    pub fn with_confirmation(mut self) -> Responder {
+        self.confirm = true;
+        self
+    }
+
+    /// Hold back the key until the initiator has signed the transcript with the
+    /// long-term key behind `verifier`.
+    pub fn with_verifier<V: Verifier + Send + 'static>(mut self, verifier: V) -> Responder {
+        self.verifier = Some(Box::new(verifier));
+        self
+    }
+
+    /// Mix the pre-shared key `psk`, known to the initiator as `id`, into the
+    /// session key. `id` must be 1 to 255 bytes and `psk` non-empty; it
+    /// should be 32 uniformly random bytes.
+    pub fn with_psk(mut self, id: &[u8], psk: &[u8]) -> Result<Responder, Error> {
+        self.psk = Some(Psk::new(id, psk)?);
+        Ok(self)
+    }
+
+    /// Accept resumption tickets sealed under `key` that are still valid at
+    /// `now`, in seconds since the Unix epoch.
+    pub fn with_ticket_key(mut self, key: &TicketKey, now: u64) -> Responder {
+        self.tickets = Some((key.clone(), now));
+        self
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Consume the initiator's framed offer, pick a parameter set, derive the
+    /// shared key and return the framed reply to send.
+    ///
+    /// A malformed offer, or one with no acceptable parameter set, fails the
+    /// handshake.
+    pub fn read_offer<R: Rng>(&mut self, rng: R, offer: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.state != State::Start {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        let result = match frame::decode_resume(offer) {
+            Err(Error::UnexpectedMessage) => self.answer_share(rng, offer),
+            resume => resume.and_then(|(_, opaque)| self.answer_resume(rng, offer, opaque))
+        };
+        match result {
+            Ok(reply) => {
+                self.transcript.update(offer);
+                self.transcript.update(&reply);
+                self.state = self.held_back(false);
+                Ok(reply)
+            },
+            Err(err) => {
+                self.state = State::Failed;
+                Err(err)
+            }
+        }
+    }
+
+    fn answer_share<R: Rng>(&mut self, rng: R, offer: &[u8]) -> Result<Vec<u8>, Error> {
+        let (shares, psk_id) = frame::decode_offer(offer)?;
+        match (self.psk.as_ref(), psk_id) {
+            (Some(psk), Some(id)) if psk.id == id => (),
+            (None, None) => (),
+            _ => return Err(Error::UnknownPskIdentity)
+        }
+        let offered = shares.iter().map(|&(alg, _)| alg).collect::<Vec<_>>();
+        let alg = frame::select(&offered, &self.algorithms)?;
+        let pk = &shares[offered.iter().position(|&x| x == alg).unwrap()].1;
+
+        let (mut key, mut msg) = ([0; 32], [0; SENDBBYTES]);
+        ::sharedb_alg(&mut Workspace::new(), alg, rng, &mut key, &pk.0, &mut msg);
+        let reply = frame::encode_reply(alg, &Reply(msg));
+        session_key(&key, self.psk.as_ref(), offer, &reply, &self.context, &mut self.key);
+        self.algorithm = Some(alg);
+        Ok(reply)
+    }
+
+    fn answer_resume<R: Rng>(&mut self, mut rng: R, offer: &[u8], opaque: &[u8]) -> Result<Vec<u8>, Error> {
+        let contents = match self.tickets {
+            Some((ref key, now)) => ticket::open(key, opaque, now)?,
+            None => return Err(Error::InvalidTicket)
+        };
+        if !self.algorithms.contains(&contents.algorithm) {
+            return Err(Error::UnsupportedAlgorithm);
+        }
+
+        let mut nonce = [0; 32];
+        rng.fill_bytes(&mut nonce);
+        let reply = frame::encode_resumed(&nonce);
+        kdf::session_key(&contents.secret, offer, &reply, &self.context, &mut self.key);
+        self.algorithm = Some(contents.algorithm);
+        Ok(reply)
+    }
+
+    /// The negotiated parameter set, once the handshake is complete.
+    pub fn algorithm(&self) -> Option<Algorithm> {
+        self.algorithm
+    }
+
+    /// The session key, once the handshake is complete.
+    pub fn key(&self) -> Option<&[u8; 32]> {
+        if self.state == State::Complete { Some(&self.key) } else { None }
+    }
+
+    /// Derive `len` bytes of key material for `label` and `context`; see
+    /// `kdf::export`.
+    pub fn export(&self, label: &[u8], context: &[u8], len: usize) -> Result<Vec<u8>, Error> {
+        let key = self.key().ok_or(Error::Incomplete)?;
+        let mut output = vec![0; len];
+        kdf::export(key, label, context, &mut output);
+        Ok(output)
+    }
+
+    /// Seal a resumption ticket for the initiator under `key`, valid from
+    /// `now` for `lifetime` seconds, at most `ticket::MAX_LIFETIME`.
+    pub fn ticket<R: Rng>(&self, rng: R, key: &TicketKey, now: u64, lifetime: u32) -> Result<Vec<u8>, Error> {
+        let session_key = self.key().ok_or(Error::Incomplete)?;
+        let mut contents = ticket::Contents {
+            issued: now,
+            lifetime: lifetime.min(ticket::MAX_LIFETIME),
+            algorithm: self.algorithm.ok_or(Error::Incomplete)?,
+            secret: [0; 32]
+        };
+        kdf::resumption_secret(session_key, &self.transcript.hash(), &mut contents.secret);
+        Ok(frame::encode_ticket(contents.lifetime, &ticket::seal(rng, key, &contents)))
+    }
+
+    /// The state once the key is derived, or once the peer's signature has
+    /// been checked.
+    fn held_back(&self, signed: bool) -> State {
+        if self.verifier.is_some() && !signed {
+            State::AwaitingSignature
+        } else if self.confirm {
+            State::AwaitingConfirmation
+        } else {
+            State::Complete
+        }
+    }
+
+    /// Sign the transcript for the initiator, once the key is derived.
+    pub fn signature<S: Signer + ?Sized>(&self, signer: &mut S) -> Result<Vec<u8>, Error> {
+        match self.state {
+            State::AwaitingSignature | State::AwaitingConfirmation | State::Complete =>
+                signature(signer, RESPONDER, &self.transcript),
+            _ => Err(Error::UnexpectedMessage)
+        }
+    }
+
+    /// Check the initiator's signature.
+    ///
+    /// A bad signature fails the handshake.
+    pub fn read_signature(&mut self, msg: &[u8]) -> Result<(), Error> {
+        if self.state != State::AwaitingSignature {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        let result = match self.verifier {
+            Some(ref verifier) => check_signature(&**verifier, INITIATOR, &self.transcript, msg),
+            None => Err(Error::UnexpectedMessage)
+        };
+        match result {
+            Ok(()) => {
+                self.state = self.held_back(true);
+                Ok(())
+            },
+            Err(err) => {
+                self.state = State::Failed;
+                Err(err)
+            }
+        }
+    }
+
+    /// The confirmation message for the initiator, once the key is derived.
+    pub fn confirmation(&self) -> Result<Vec<u8>, Error> {
+        match self.state {
+            State::AwaitingSignature | State::AwaitingConfirmation | State::Complete =>
+                Ok(confirmation(&self.key, RESPONDER, &self.transcript)),
+            _ => Err(Error::UnexpectedMessage)
+        }
+    }
+
+    /// Check the initiator's confirmation message and release the key.
+    ///
+    /// A mismatch fails the handshake.
+    pub fn read_confirmation(&mut self, msg: &[u8]) -> Result<(), Error> {
+        if self.state != State::AwaitingConfirmation {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        match check_confirmation(&self.key, INITIATOR, &self.transcript, msg) {
+            Ok(()) => {
+                self.state = State::Complete;
+                Ok(())
+            },
+            Err(err) => {
+                self.state = State::Failed;
+                Err(err)
+            }
+        }
+    }
+
+    /// Hash of the messages exchanged so far.
+    pub fn transcript_hash(&self) -> [u8; 32] {
+        self.transcript.hash()
+    }
+}
+
+impl Default for Responder {
+    fn default() -> Responder {
+        Responder::new()
+    }
+}
+
+
+#[test]
+fn test_handshake() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x6861, 0x6e64, 0x7368, 0x616b]);
+    let (mut alice, mut bob) = (Initiator::new(), Responder::new());
+    assert_eq!(alice.transcript_hash(), bob.transcript_hash());
+
+    let offer = alice.offer(&mut rng).unwrap();
+    assert_eq!(alice.state(), State::AwaitingReply);
+    assert_eq!(alice.key(), None);
+    assert_eq!(alice.export(b"client write", b"", 32), Err(Error::Incomplete));
+    assert_ne!(alice.transcript_hash(), bob.transcript_hash());
+
+    let reply = bob.read_offer(&mut rng, &offer).unwrap();
+    assert_eq!(bob.state(), State::Complete);
+
+    alice.read_reply(&reply).unwrap();
+    assert_eq!(alice.state(), State::Complete);
+    assert_eq!(alice.algorithm(), Some(Algorithm::CURRENT));
+    assert_eq!(bob.algorithm(), Some(Algorithm::CURRENT));
+    assert!(alice.key().is_some());
+    assert_eq!(alice.key(), bob.key());
+    assert_eq!(alice.transcript_hash(), bob.transcript_hash());
+
+    let client = alice.export(b"client write", b"", 48).unwrap();
+    assert_eq!(client.len(), 48);
+    assert_eq!(client, bob.export(b"client write", b"", 48).unwrap());
+    assert_ne!(client, bob.export(b"server write", b"", 48).unwrap());
+
+    // duplicated messages
+    assert_eq!(alice.offer(&mut rng).unwrap_err(), Error::UnexpectedMessage);
+    assert_eq!(alice.read_reply(&reply), Err(Error::UnexpectedMessage));
+    assert_eq!(bob.read_offer(&mut rng, &offer).unwrap_err(), Error::UnexpectedMessage);
+    assert_eq!(alice.state(), State::Complete);
+    assert_eq!(alice.key(), bob.key());
+}
+
+#[test]
+fn test_out_of_order() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x6f72, 0x6465, 0x72]);
+    let mut alice = Initiator::new();
+    assert_eq!(alice.read_reply(&[0; SENDBBYTES]), Err(Error::UnexpectedMessage));
+    assert_eq!(alice.state(), State::Start);
+
+    // a malformed reply fails the handshake for good
+    let offer = alice.offer(&mut rng).unwrap();
+    assert_eq!(alice.read_reply(&[frame::VERSION, 2, 1, 0]), Err(Error::InvalidLength));
+    assert_eq!(alice.state(), State::Failed);
+    assert_eq!(alice.read_reply(&[0; SENDBBYTES]), Err(Error::UnexpectedMessage));
+    assert_eq!(alice.key(), None);
+
+    let mut bob = Responder::new();
+    let bad = frame::encode_offer(&[(Algorithm::CURRENT, PublicKey([0xff; SENDABYTES]))], None);
+    assert_eq!(bob.read_offer(&mut rng, &bad).unwrap_err(), Error::InvalidKey);
+    assert_eq!(bob.state(), State::Failed);
+    assert_eq!(bob.read_offer(&mut rng, &offer).unwrap_err(), Error::UnexpectedMessage);
+    assert_eq!(bob.key(), None);
+}
+
+#[test]
+fn test_negotiation() {
+    use rand::{ SeedableRng, ChaChaRng };
+    use ::params::Algorithm::*;
+
+    let mut rng = ChaChaRng::from_seed(&[0x6e65, 0x676f]);
+
+    // the responder only speaks the reference sampler
+    let mut alice = Initiator::new();
+    let mut bob = Responder::with_algorithms(&[NewHope1024]).unwrap();
+    let offer = alice.offer(&mut rng).unwrap();
+    let reply = bob.read_offer(&mut rng, &offer).unwrap();
+    alice.read_reply(&reply).unwrap();
+    assert_eq!(alice.algorithm(), Some(NewHope1024));
+    assert_eq!(alice.key(), bob.key());
+    assert_eq!(alice.transcript_hash(), bob.transcript_hash());
+
+    // a peer offering only the other sampler is refused, not misread
+    let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+    ::keygen(&mut rng, &mut sk, &mut pk);
+    let offer = frame::encode_offer(&[(NewHope1024Tor, PublicKey(pk))], None);
+    let mut bob = Responder::with_algorithms(&[NewHope1024]).unwrap();
+    assert_eq!(bob.read_offer(&mut rng, &offer).unwrap_err(), Error::NoCommonAlgorithm);
+    assert_eq!(bob.state(), State::Failed);
+
+    // a reply for a parameter set that wasn't offered
+    let mut alice = Initiator::with_algorithms(&[NewHope1024]).unwrap();
+    alice.offer(&mut rng).unwrap();
+    let reply = frame::encode_reply(NewHope1024Tor, &Reply([0; SENDBBYTES]));
+    assert_eq!(alice.read_reply(&reply), Err(Error::UnsupportedAlgorithm));
+    assert_eq!(alice.state(), State::Failed);
+
+    assert_eq!(Initiator::with_algorithms(&[]).err(), Some(Error::NoCommonAlgorithm));
+    let mut alice = Initiator::with_algorithms(&[NewHope1024, NewHope1024]).unwrap();
+    assert_eq!(frame::decode_offer(&alice.offer(&mut rng).unwrap()).unwrap().0.len(), 1);
+    if !cfg!(feature = "tor") {
+        assert_eq!(Initiator::with_algorithms(&[NewHope1024Tor]).err(), Some(Error::UnsupportedAlgorithm));
+        assert_eq!(Responder::with_algorithms(&[NewHope1024Tor]).err(), Some(Error::UnsupportedAlgorithm));
+    }
+}
+
+#[test]
+fn test_context() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x636f, 0x6e74, 0x6578, 0x74]);
+    let run = |rng: &mut ChaChaRng, a: &[u8], b: &[u8]| {
+        let mut alice = Initiator::new().with_context(a);
+        let mut bob = Responder::new().with_context(b);
+        let offer = alice.offer(&mut *rng).unwrap();
+        let reply = bob.read_offer(&mut *rng, &offer).unwrap();
+        alice.read_reply(&reply).unwrap();
+        (*alice.key().unwrap(), *bob.key().unwrap())
+    };
+
+    let (a, b) = run(&mut rng, b"app v1", b"app v1");
+    assert_eq!(a, b);
+    let (a, b) = run(&mut rng, b"app v1", b"app v2");
+    assert_ne!(a, b);
+}
+
+#[test]
+fn test_confirmation() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x636f, 0x6e66, 0x6972, 0x6d]);
+    let handshake = |rng: &mut ChaChaRng, tamper: bool| {
+        let mut alice = Initiator::new().with_confirmation();
+        let mut bob = Responder::new().with_confirmation();
+        let offer = alice.offer(&mut *rng).unwrap();
+        assert_eq!(alice.confirmation().unwrap_err(), Error::UnexpectedMessage);
+        let mut reply = bob.read_offer(&mut *rng, &offer).unwrap();
+        if tamper {
+            // flip reconciliation bits: the reply still parses
+            let last = reply.len() - 1;
+            reply[last] ^= 0x55;
+        }
+        alice.read_reply(&reply).unwrap();
+        (alice, bob)
+    };
+
+    let (mut alice, mut bob) = handshake(&mut rng, false);
+    assert_eq!(alice.state(), State::AwaitingConfirmation);
+    assert_eq!(bob.state(), State::AwaitingConfirmation);
+    assert_eq!(alice.key(), None);
+    assert_eq!(bob.export(b"label", b"", 16), Err(Error::Incomplete));
+
+    let bob_confirmation = bob.confirmation().unwrap();
+    alice.read_confirmation(&bob_confirmation).unwrap();
+    assert_eq!(alice.state(), State::Complete);
+    bob.read_confirmation(&alice.confirmation().unwrap()).unwrap();
+    assert_eq!(bob.state(), State::Complete);
+    assert_eq!(alice.key(), bob.key());
+
+    // duplicated, or one party's own message reflected back
+    assert_eq!(alice.read_confirmation(&bob_confirmation), Err(Error::UnexpectedMessage));
+    let (mut alice, _) = handshake(&mut rng, false);
+    let reflected = alice.confirmation().unwrap();
+    assert_eq!(alice.read_confirmation(&reflected), Err(Error::KeyConfirmationFailed));
+    assert_eq!(alice.state(), State::Failed);
+
+    // a tampered reply leaves the parties with different keys
+    let (mut alice, mut bob) = handshake(&mut rng, true);
+    let (alice_confirmation, bob_confirmation) = (alice.confirmation().unwrap(), bob.confirmation().unwrap());
+    assert_eq!(alice.read_confirmation(&bob_confirmation), Err(Error::KeyConfirmationFailed));
+    assert_eq!(bob.read_confirmation(&alice_confirmation), Err(Error::KeyConfirmationFailed));
+    assert_eq!(alice.key(), None);
+    assert_eq!(bob.key(), None);
+
+    // a tampered tag
+    let (mut alice, bob) = handshake(&mut rng, false);
+    let mut msg = bob.confirmation().unwrap();
+    msg[10] ^= 1;
+    assert_eq!(alice.read_confirmation(&msg), Err(Error::KeyConfirmationFailed));
+}
+
+#[test]
+fn test_signature() {
+    use rand::{ SeedableRng, ChaChaRng };
+    use ::auth::MerkleSigner;
+
+    let mut rng = ChaChaRng::from_seed(&[0x7369, 0x676e]);
+    let mut alice_signer = MerkleSigner::generate(&mut rng, 2).unwrap();
+    let mut bob_signer = MerkleSigner::generate(&mut rng, 2).unwrap();
+
+    // mutual
+    let mut alice = Initiator::new().with_verifier(bob_signer.verifier());
+    let mut bob = Responder::new().with_verifier(alice_signer.verifier());
+    let offer = alice.offer(&mut rng).unwrap();
+    let reply = bob.read_offer(&mut rng, &offer).unwrap();
+    alice.read_reply(&reply).unwrap();
+    assert_eq!(alice.state(), State::AwaitingSignature);
+    assert_eq!(bob.state(), State::AwaitingSignature);
+    assert_eq!(alice.key(), None);
+
+    let bob_signature = bob.signature(&mut bob_signer).unwrap();
+    alice.read_signature(&bob_signature).unwrap();
+    bob.read_signature(&alice.signature(&mut alice_signer).unwrap()).unwrap();
+    assert_eq!(alice.state(), State::Complete);
+    assert_eq!(alice.key(), bob.key());
+    assert_eq!(alice.read_signature(&bob_signature), Err(Error::UnexpectedMessage));
+
+    // only the responder authenticates, then both confirm
+    let mut alice = Initiator::new().with_verifier(bob_signer.verifier()).with_confirmation();
+    let mut bob = Responder::new().with_confirmation();
+    let offer = alice.offer(&mut rng).unwrap();
+    let reply = bob.read_offer(&mut rng, &offer).unwrap();
+    alice.read_reply(&reply).unwrap();
+    assert_eq!(bob.state(), State::AwaitingConfirmation);
+    let bob_confirmation = bob.confirmation().unwrap();
+    alice.read_signature(&bob.signature(&mut bob_signer).unwrap()).unwrap();
+    assert_eq!(alice.state(), State::AwaitingConfirmation);
+    alice.read_confirmation(&bob_confirmation).unwrap();
+    bob.read_confirmation(&alice.confirmation().unwrap()).unwrap();
+    assert_eq!(alice.key(), bob.key());
+
+    // a relay that runs its own handshake with each side can't sign as bob
+    let mut alice = Initiator::new().with_verifier(bob_signer.verifier());
+    let mut mallory = Responder::new();
+    let mut mallory_signer = MerkleSigner::generate(&mut rng, 1).unwrap();
+    let offer = alice.offer(&mut rng).unwrap();
+    let reply = mallory.read_offer(&mut rng, &offer).unwrap();
+    alice.read_reply(&reply).unwrap();
+    assert_eq!(alice.read_signature(&mallory.signature(&mut mallory_signer).unwrap()), Err(Error::BadSignature));
+    assert_eq!(alice.state(), State::Failed);
+    assert_eq!(alice.key(), None);
+
+    // nor replay bob's signature from another session, or reflect alice's own
+    let mut alice = Initiator::new().with_verifier(bob_signer.verifier());
+    let offer = alice.offer(&mut rng).unwrap();
+    let reply = Responder::new().read_offer(&mut rng, &offer).unwrap();
+    alice.read_reply(&reply).unwrap();
+    assert_eq!(alice.read_signature(&bob_signature), Err(Error::BadSignature));
+
+    let mut alice = Initiator::new().with_verifier(alice_signer.verifier());
+    let offer = alice.offer(&mut rng).unwrap();
+    let reply = Responder::new().read_offer(&mut rng, &offer).unwrap();
+    alice.read_reply(&reply).unwrap();
+    let reflected = alice.signature(&mut alice_signer).unwrap();
+    assert_eq!(alice.read_signature(&reflected), Err(Error::BadSignature));
+}
+
+#[test]
+fn test_psk() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x7073, 0x6b]);
+    let handshake = |rng: &mut ChaChaRng, alice: Initiator, bob: Responder| {
+        let (mut alice, mut bob) = (alice.with_confirmation(), bob.with_confirmation());
+        let offer = alice.offer(&mut *rng).unwrap();
+        let reply = bob.read_offer(&mut *rng, &offer)?;
+        alice.read_reply(&reply).unwrap();
+        let (alice_confirmation, bob_confirmation) = (alice.confirmation().unwrap(), bob.confirmation().unwrap());
+        alice.read_confirmation(&bob_confirmation)?;
+        bob.read_confirmation(&alice_confirmation)?;
+        assert_eq!(alice.key(), bob.key());
+        Ok(*alice.key().unwrap())
+    };
+
+    let psk = [0x42; 32];
+    let with_psk = handshake(
+        &mut rng,
+        Initiator::new().with_psk(b"link 7", &psk).unwrap(),
+        Responder::new().with_psk(b"link 7", &psk).unwrap()
+    ).unwrap();
+    let without = handshake(&mut rng, Initiator::new(), Responder::new()).unwrap();
+    assert_ne!(with_psk, without);
+
+    // the same identifier with a different key yields different session keys
+    assert_eq!(
+        handshake(
+            &mut rng,
+            Initiator::new().with_psk(b"link 7", &psk).unwrap(),
+            Responder::new().with_psk(b"link 7", &[0x43; 32]).unwrap()
+        ),
+        Err(Error::KeyConfirmationFailed)
+    );
+
+    // a mismatched or missing identifier is refused outright
+    for &(alice_id, bob_id) in &[(Some(&b"link 7"[..]), Some(&b"link 8"[..])), (Some(b"link 7"), None), (None, Some(b"link 7"))] {
+        let alice = match alice_id {
+            Some(id) => Initiator::new().with_psk(id, &psk).unwrap(),
+            None => Initiator::new()
+        };
+        let bob = match bob_id {
+            Some(id) => Responder::new().with_psk(id, &psk).unwrap(),
+            None => Responder::new()
+        };
+        assert_eq!(handshake(&mut rng, alice, bob), Err(Error::UnknownPskIdentity));
+    }
+
+    assert!(Initiator::new().with_psk(b"", &psk).is_err());
+    assert!(Initiator::new().with_psk(&[0; 256], &psk).is_err());
+    assert!(Responder::new().with_psk(b"link 7", b"").is_err());
+}
+
+#[test]
+fn test_resumption() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x7265, 0x7375, 0x6d65]);
+    let ticket_key = TicketKey::generate(&mut rng);
+
+    // a full handshake, through a responder that also accepts tickets
+    let (mut alice, mut bob) = (Initiator::new(), Responder::new().with_ticket_key(&ticket_key, 1000));
+    assert_eq!(bob.ticket(&mut rng, &ticket_key, 1000, 3600), Err(Error::Incomplete));
+    let offer = alice.offer(&mut rng).unwrap();
+    let reply = bob.read_offer(&mut rng, &offer).unwrap();
+    assert_eq!(alice.read_ticket(&frame::encode_ticket(3600, b"early"), 1000).err(), Some(Error::Incomplete));
+    alice.read_reply(&reply).unwrap();
+
+    let msg = bob.ticket(&mut rng, &ticket_key, 1000, 3600).unwrap();
+    let ticket = alice.read_ticket(&msg, 1005).unwrap();
+    assert_eq!(ticket.expires(), 4605);
+    assert!(!ticket.is_expired(4604));
+    assert_eq!(ticket.algorithm(), alice.algorithm().unwrap());
+    let long = bob.ticket(&mut rng, &ticket_key, 1000, u32::MAX).unwrap();
+    assert_eq!(frame::decode_ticket(&long).unwrap().0, ::ticket::MAX_LIFETIME);
+
+    let resume = |rng: &mut ChaChaRng, ticket: &Ticket, bob: Responder| {
+        let mut alice = Initiator::resume(ticket).with_confirmation();
+        let mut bob = bob.with_confirmation();
+        let offer = alice.offer(&mut *rng).unwrap();
+        assert!(offer.len() < 200);
+        let reply = bob.read_offer(&mut *rng, &offer)?;
+        assert_eq!(reply.len(), 35);
+        alice.read_reply(&reply).unwrap();
+        let bob_confirmation = bob.confirmation().unwrap();
+        alice.read_confirmation(&bob_confirmation).unwrap();
+        bob.read_confirmation(&alice.confirmation().unwrap()).unwrap();
+        assert_eq!(alice.key(), bob.key());
+        assert_eq!(alice.algorithm(), bob.algorithm());
+        Ok((alice, bob))
+    };
+
+    // resumed sessions get fresh keys, and can issue tickets of their own
+    let (alice2, bob2) = resume(&mut rng, &ticket, Responder::new().with_ticket_key(&ticket_key, 2000)).unwrap();
+    assert_ne!(alice2.key(), alice.key());
+    let (alice3, _) = resume(&mut rng, &ticket, Responder::new().with_ticket_key(&ticket_key, 2000)).unwrap();
+    assert_ne!(alice3.key(), alice2.key());
+    let msg = bob2.ticket(&mut rng, &ticket_key, 2000, 60).unwrap();
+    let ticket2 = alice2.read_ticket(&msg, 2000).unwrap();
+    assert!(resume(&mut rng, &ticket2, Responder::new().with_ticket_key(&ticket_key, 2059)).is_ok());
+
+    // expired, under another key, or to a responder that takes no tickets
+    let other_key = TicketKey::generate(&mut rng);
+    for bob in [
+        Responder::new().with_ticket_key(&ticket_key, 4600),
+        Responder::new().with_ticket_key(&ticket_key, 999),
+        Responder::new().with_ticket_key(&other_key, 2000),
+        Responder::new()
+    ] {
+        assert_eq!(resume(&mut rng, &ticket, bob).err(), Some(Error::InvalidTicket));
+    }
+
+    let mut bob = Responder::new().with_ticket_key(&ticket_key, 2000);
+    let mut offer = Initiator::resume(&ticket).offer(&mut rng).unwrap();
+    let last = offer.len() - 1;
+    offer[last] ^= 1;
+    assert_eq!(bob.read_offer(&mut rng, &offer), Err(Error::InvalidTicket));
+    assert_eq!(bob.state(), State::Failed);
+}
@@ -0,0 +1,20 @@
+//! Low-level primitives, exported for differential testing against the
+//! reference implementation. Not a stable API.
+
+pub use ::ntt::{ bitrev_vector, mul_coefficients, ntt };
+pub use ::error_correction::{ helprec, rec };
+pub use ::poly::{ poly_frombytes, poly_tobytes };
+#[cfg(feature = "tor")] pub use ::batcher::batcher84;
+
+
+pub fn poly_uniform(a: &mut [u16], seed: &[u8]) {
+    ::poly::uniform(a, seed)
+}
+
+pub fn poly_ntt(r: &mut [u16]) {
+    ::poly::ntt(r)
+}
+
+pub fn poly_invntt(r: &mut [u16]) {
+    ::poly::invntt(r)
+}
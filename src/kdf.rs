@@ -0,0 +1,394 @@
+//! Key derivation bound to the handshake transcript.
+//!
+//! `sharedb` and `shareda` hash the reconciled key on its own, so nothing
+//! ties it to the messages that produced it. `session_key` feeds the key,
+//! both framed messages and the caller's context through cSHAKE256
+//! (NIST SP 800-185), customised with the protocol label. Every input is
+//! length-prefixed, so distinct transcripts never share an encoding.
+//!
+//! `psk_session_key` does the same with a pre-shared key fed in alongside
+//! the reconciled one, under its own label: the result stays secret as long
+//! as either input does.
+//!
+//! `export` then stretches a session key into any number of independent keys
+//! with KMAC256, in the manner of TLS exporters, and `confirmation_tag` MACs
+//! the transcript for key confirmation. `resumption_secret` derives the
+//! secret a resumption ticket carries.
+
+use tiny_keccak::Keccak;
+
+
+const CSHAKE256_RATE: usize = 136;
+const CSHAKE_DELIM: u8 = 0x04;
+
+/// Function name for every cSHAKE256 instance in this crate.
+pub const FUNCTION_NAME: &[u8] = b"NewHope";
+
+/// Customisation string of `session_key`.
+pub const SESSION_LABEL: &[u8] = b"newhope session key v1";
+
+/// Customisation string of `psk_session_key`.
+pub const PSK_SESSION_LABEL: &[u8] = b"newhope psk session key v1";
+
+/// Customisation string of `resumption_secret`.
+pub const RESUMPTION_LABEL: &[u8] = b"newhope resumption v1";
+
+/// Customisation string of `export`.
+pub const EXPORTER_LABEL: &[u8] = b"newhope exporter v1";
+
+/// Customisation string of `confirmation_tag`.
+pub const CONFIRMATION_LABEL: &[u8] = b"newhope confirmation v1";
+
+/// `left_encode(x)`: the big-endian bytes of `x`, prefixed with their count.
+fn left_encode(x: u64, buf: &mut [u8; 9]) -> &[u8] {
+    let n = (8 - (x.leading_zeros() as usize / 8)).max(1);
+    buf[0] = n as u8;
+    for i in 0..n {
+        buf[1 + i] = (x >> (8 * (n - 1 - i))) as u8;
+    }
+    &buf[..1 + n]
+}
+
+/// `right_encode(x)`: the big-endian bytes of `x`, followed by their count.
+fn right_encode(x: u64, buf: &mut [u8; 9]) -> &[u8] {
+    let n = (8 - (x.leading_zeros() as usize / 8)).max(1);
+    buf[n] = n as u8;
+    for i in 0..n {
+        buf[n - 1 - i] = (x >> (8 * i)) as u8;
+    }
+    &buf[..n + 1]
+}
+
+
+/// Absorb `bytepad(encode_string(s_1) || ... || encode_string(s_n), rate)`.
+fn bytepad(keccak: &mut Keccak, strings: &[&[u8]]) {
+    let mut buf = [0; 9];
+    let rate = left_encode(CSHAKE256_RATE as u64, &mut buf);
+    keccak.update(rate);
+    let mut len = rate.len();
+    for s in strings {
+        let prefix = left_encode(8 * s.len() as u64, &mut buf);
+        keccak.update(prefix);
+        keccak.update(s);
+        len += prefix.len() + s.len();
+    }
+
+    let pad = [0; CSHAKE256_RATE];
+    keccak.update(&pad[..(CSHAKE256_RATE - len % CSHAKE256_RATE) % CSHAKE256_RATE]);
+}
+
+
+/// cSHAKE256 with function name `N` and customisation string `S`.
+#[derive(Clone)]
+pub struct CShake256(Keccak);
+
+impl CShake256 {
+    pub fn new(name: &[u8], custom: &[u8]) -> CShake256 {
+        if name.is_empty() && custom.is_empty() {
+            return CShake256(Keccak::new_shake256());
+        }
+
+        let mut keccak = Keccak::new(CSHAKE256_RATE, CSHAKE_DELIM);
+        bytepad(&mut keccak, &[name, custom]);
+        CShake256(keccak)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    /// Absorb `encode_string(data)`, so that consecutive strings stay apart.
+    pub fn update_string(&mut self, data: &[u8]) {
+        let mut buf = [0; 9];
+        self.0.update(left_encode(8 * data.len() as u64, &mut buf));
+        self.0.update(data);
+    }
+
+    pub fn finalize(self, output: &mut [u8]) {
+        self.0.finalize(output);
+    }
+}
+
+
+/// KMAC256 with key `K` and customisation string `S`; the output length is
+/// part of the input, so different lengths give unrelated outputs.
+#[derive(Clone)]
+pub struct Kmac256(CShake256);
+
+impl Kmac256 {
+    pub fn new(key: &[u8], custom: &[u8]) -> Kmac256 {
+        let mut cshake = CShake256::new(b"KMAC", custom);
+        bytepad(&mut cshake.0, &[key]);
+        Kmac256(cshake)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    /// Absorb `encode_string(data)`.
+    pub fn update_string(&mut self, data: &[u8]) {
+        self.0.update_string(data);
+    }
+
+    pub fn finalize(mut self, output: &mut [u8]) {
+        let mut buf = [0; 9];
+        self.0.update(right_encode(8 * output.len() as u64, &mut buf));
+        self.0.finalize(output);
+    }
+}
+
+
+/// Derive a session key from the `sharedb`/`shareda` key, the framed offer
+/// and reply, and optional application context.
+pub fn session_key(key: &[u8; 32], offer: &[u8], reply: &[u8], context: &[u8], output: &mut [u8; 32]) {
+    let mut cshake = CShake256::new(FUNCTION_NAME, SESSION_LABEL);
+    cshake.update_string(key);
+    cshake.update_string(offer);
+    cshake.update_string(reply);
+    cshake.update_string(context);
+    cshake.finalize(output);
+}
+
+/// `session_key` with a pre-shared key mixed in; the framed offer names it.
+pub fn psk_session_key(key: &[u8; 32], psk: &[u8], offer: &[u8], reply: &[u8], context: &[u8], output: &mut [u8; 32]) {
+    let mut cshake = CShake256::new(FUNCTION_NAME, PSK_SESSION_LABEL);
+    cshake.update_string(key);
+    cshake.update_string(psk);
+    cshake.update_string(offer);
+    cshake.update_string(reply);
+    cshake.update_string(context);
+    cshake.finalize(output);
+}
+
+/// Fill `output` with key material for `label` and `context`, derived from a
+/// session key.
+pub fn export(key: &[u8; 32], label: &[u8], context: &[u8], output: &mut [u8]) {
+    let mut kmac = Kmac256::new(key, EXPORTER_LABEL);
+    kmac.update_string(label);
+    kmac.update_string(context);
+    kmac.finalize(output);
+}
+
+/// KMAC256 of the transcript hash under the session key.
+pub fn resumption_secret(key: &[u8; 32], transcript: &[u8; 32], output: &mut [u8; 32]) {
+    let mut kmac = Kmac256::new(key, RESUMPTION_LABEL);
+    kmac.update_string(transcript);
+    kmac.finalize(output);
+}
+
+/// MAC `transcript` for `role` under a confirmation key derived from a
+/// session key.
+pub fn confirmation_tag(key: &[u8; 32], role: &[u8], transcript: &[u8; 32], tag: &mut [u8; 32]) {
+    let mut confirmation_key = [0; 32];
+    let mut kmac = Kmac256::new(key, CONFIRMATION_LABEL);
+    kmac.update_string(b"key");
+    kmac.finalize(&mut confirmation_key);
+
+    let mut kmac = Kmac256::new(&confirmation_key, CONFIRMATION_LABEL);
+    kmac.update_string(role);
+    kmac.update_string(transcript);
+    kmac.finalize(tag);
+}
+
+/// Compare `a` and `b` in time that depends only on their lengths.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y));
+    (diff as u32).wrapping_sub(1) >> 31 == 1
+}
+
+/// Overwrite `buf` with zeros through volatile writes, which the optimiser
+/// can't drop as dead stores.
+pub(crate) fn zeroize(buf: &mut [u8]) {
+    use std::ptr;
+    use std::sync::atomic::{ compiler_fence, Ordering };
+
+    for b in buf.iter_mut() {
+        unsafe { ptr::write_volatile(b, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+
+#[test]
+fn test_encode() {
+    let mut buf = [0; 9];
+    assert_eq!(left_encode(0, &mut buf), &[1, 0]);
+    assert_eq!(left_encode(136, &mut buf), &[1, 136]);
+    assert_eq!(left_encode(256, &mut buf), &[2, 1, 0]);
+    assert_eq!(right_encode(0, &mut buf), &[0, 1]);
+    assert_eq!(right_encode(512, &mut buf), &[2, 0, 2]);
+    assert_eq!(left_encode(!0, &mut buf), &[8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+}
+
+#[test]
+fn test_cshake256() {
+    // NIST SP 800-185 cSHAKE samples #3 and #4
+    let expected3 = [
+        0xd0, 0x08, 0x82, 0x8e, 0x2b, 0x80, 0xac, 0x9d, 0x22, 0x18, 0xff, 0xee, 0x1d, 0x07, 0x0c, 0x48,
+        0xb8, 0xe4, 0xc8, 0x7b, 0xff, 0x32, 0xc9, 0x69, 0x9d, 0x5b, 0x68, 0x96, 0xee, 0xe0, 0xed, 0xd1,
+        0x64, 0x02, 0x0e, 0x2b, 0xe0, 0x56, 0x08, 0x58, 0xd9, 0xc0, 0x0c, 0x03, 0x7e, 0x34, 0xa9, 0x69,
+        0x37, 0xc5, 0x61, 0xa7, 0x4c, 0x41, 0x2b, 0xb4, 0xc7, 0x46, 0x46, 0x95, 0x27, 0x28, 0x1c, 0x8c
+    ];
+    let mut output = [0; 64];
+    let mut cshake = CShake256::new(b"", b"Email Signature");
+    cshake.update(&[0, 1, 2, 3]);
+    cshake.finalize(&mut output);
+    assert_eq!(&output[..], &expected3[..]);
+
+    let expected4 = [
+        0x07, 0xdc, 0x27, 0xb1, 0x1e, 0x51, 0xfb, 0xac, 0x75, 0xbc, 0x7b, 0x3c, 0x1d, 0x98, 0x3e, 0x8b,
+        0x4b, 0x85, 0xfb, 0x1d, 0xef, 0xaf, 0x21, 0x89, 0x12, 0xac, 0x86, 0x43, 0x02, 0x73, 0x09, 0x17,
+        0x27, 0xf4, 0x2b, 0x17, 0xed, 0x1d, 0xf6, 0x3e, 0x8e, 0xc1, 0x18, 0xf0, 0x4b, 0x23, 0x63, 0x3c,
+        0x1d, 0xfb, 0x15, 0x74, 0xc8, 0xfb, 0x55, 0xcb, 0x45, 0xda, 0x8e, 0x25, 0xaf, 0xb0, 0x92, 0xbb
+    ];
+    let data = (0..200).collect::<Vec<u8>>();
+    let mut cshake = CShake256::new(b"", b"Email Signature");
+    cshake.update(&data);
+    cshake.finalize(&mut output);
+    assert_eq!(&output[..], &expected4[..]);
+
+    // with empty N and S, cSHAKE256 is SHAKE256
+    let mut shake = Keccak::new_shake256();
+    shake.update(&data);
+    let mut expected = [0; 64];
+    shake.finalize(&mut expected);
+    let mut cshake = CShake256::new(b"", b"");
+    cshake.update(&data);
+    cshake.finalize(&mut output);
+    assert_eq!(&output[..], &expected[..]);
+}
+
+#[test]
+fn test_session_key() {
+    let key = [7; 32];
+    let (mut a, mut b) = ([0; 32], [0; 32]);
+
+    session_key(&key, b"offer", b"reply", b"", &mut a);
+    session_key(&key, b"offer", b"reply", b"", &mut b);
+    assert_eq!(a, b);
+
+    // each input changes the key, and moving bytes between fields does too
+    for &(k, offer, reply, context) in &[
+        ([8; 32], &b"offer"[..], &b"reply"[..], &b""[..]),
+        (key, b"offeR", b"reply", b""),
+        (key, b"offer", b"reply!", b""),
+        (key, b"offer", b"reply", b"app"),
+        (key, b"offerr", b"eply", b"")
+    ] {
+        session_key(&k, offer, reply, context, &mut b);
+        assert_ne!(a, b);
+    }
+
+    // the pre-shared key changes the key, and never collides with no key
+    psk_session_key(&key, b"psk", b"offer", b"reply", b"", &mut b);
+    assert_ne!(a, b);
+    let c = b;
+    psk_session_key(&key, b"", b"offer", b"reply", b"", &mut b);
+    assert_ne!(a, b);
+    psk_session_key(&key, b"psK", b"offer", b"reply", b"", &mut b);
+    assert_ne!(c, b);
+}
+
+#[test]
+fn test_kmac256() {
+    // NIST SP 800-185 KMAC samples #4 and #6
+    let key = (0x40..0x60).collect::<Vec<u8>>();
+    let expected4 = [
+        0x20, 0xc5, 0x70, 0xc3, 0x13, 0x46, 0xf7, 0x03, 0xc9, 0xac, 0x36, 0xc6, 0x1c, 0x03, 0xcb, 0x64,
+        0xc3, 0x97, 0x0d, 0x0c, 0xfc, 0x78, 0x7e, 0x9b, 0x79, 0x59, 0x9d, 0x27, 0x3a, 0x68, 0xd2, 0xf7,
+        0xf6, 0x9d, 0x4c, 0xc3, 0xde, 0x9d, 0x10, 0x4a, 0x35, 0x16, 0x89, 0xf2, 0x7c, 0xf6, 0xf5, 0x95,
+        0x1f, 0x01, 0x03, 0xf3, 0x3f, 0x4f, 0x24, 0x87, 0x10, 0x24, 0xd9, 0xc2, 0x77, 0x73, 0xa8, 0xdd
+    ];
+    let mut output = [0; 64];
+    let mut kmac = Kmac256::new(&key, b"My Tagged Application");
+    kmac.update(&[0, 1, 2, 3]);
+    kmac.finalize(&mut output);
+    assert_eq!(&output[..], &expected4[..]);
+
+    let expected6 = [
+        0xb5, 0x86, 0x18, 0xf7, 0x1f, 0x92, 0xe1, 0xd5, 0x6c, 0x1b, 0x8c, 0x55, 0xdd, 0xd7, 0xcd, 0x18,
+        0x8b, 0x97, 0xb4, 0xca, 0x4d, 0x99, 0x83, 0x1e, 0xb2, 0x69, 0x9a, 0x83, 0x7d, 0xa2, 0xe4, 0xd9,
+        0x70, 0xfb, 0xac, 0xfd, 0xe5, 0x00, 0x33, 0xae, 0xa5, 0x85, 0xf1, 0xa2, 0x70, 0x85, 0x10, 0xc3,
+        0x2d, 0x07, 0x88, 0x08, 0x01, 0xbd, 0x18, 0x28, 0x98, 0xfe, 0x47, 0x68, 0x76, 0xfc, 0x89, 0x65
+    ];
+    let data = (0..200).collect::<Vec<u8>>();
+    let mut kmac = Kmac256::new(&key, b"My Tagged Application");
+    kmac.update(&data);
+    kmac.finalize(&mut output);
+    assert_eq!(&output[..], &expected6[..]);
+}
+
+#[test]
+fn test_export() {
+    let key = [7; 32];
+    let mut a = [0; 64];
+    export(&key, b"client write", b"", &mut a);
+
+    // a shorter export is not a prefix of a longer one
+    let mut b = [0; 32];
+    export(&key, b"client write", b"", &mut b);
+    assert_ne!(&a[..32], &b[..]);
+
+    let mut c = [0; 64];
+    for &(key, label, context) in &[
+        ([8; 32], &b"client write"[..], &b""[..]),
+        (key, b"server write", b""),
+        (key, b"client write", b"1"),
+        (key, b"client writ", b"e")
+    ] {
+        export(&key, label, context, &mut c);
+        assert_ne!(&a[..], &c[..]);
+    }
+}
+
+#[test]
+fn test_confirmation_tag() {
+    let (key, transcript) = ([7; 32], [9; 32]);
+    let (mut a, mut b) = ([0; 32], [0; 32]);
+    confirmation_tag(&key, b"initiator", &transcript, &mut a);
+    confirmation_tag(&key, b"responder", &transcript, &mut b);
+    assert_ne!(a, b);
+
+    // the tag is not an export under the same key
+    let mut c = [0; 32];
+    export(&key, b"key", b"", &mut c);
+    assert_ne!(a, c);
+
+    assert!(ct_eq(&a, &a.clone()));
+    assert!(!ct_eq(&a, &b));
+    assert!(!ct_eq(&a, &a[..31]));
+    assert!(ct_eq(&[], &[]));
+}
+
+#[test]
+fn test_resumption_secret() {
+    let (key, transcript) = ([7; 32], [9; 32]);
+    let (mut a, mut b) = ([0; 32], [0; 32]);
+    resumption_secret(&key, &transcript, &mut a);
+    resumption_secret(&key, &transcript, &mut b);
+    assert_eq!(a, b);
+
+    // neither a confirmation tag nor an export under the same key
+    let mut c = [0; 32];
+    confirmation_tag(&key, b"initiator", &transcript, &mut c);
+    assert_ne!(a, c);
+    export(&key, b"key", b"", &mut c);
+    assert_ne!(a, c);
+
+    resumption_secret(&key, &[8; 32], &mut b);
+    assert_ne!(a, b);
+    resumption_secret(&[6; 32], &transcript, &mut b);
+    assert_ne!(a, b);
+}
+
+#[test]
+fn test_zeroize() {
+    let mut buf = [0xa5; 33];
+    zeroize(&mut buf[1..]);
+    assert_eq!(buf[0], 0xa5);
+    assert!(buf[1..].iter().all(|&b| b == 0));
+}
@@ -3,14 +3,32 @@
 extern crate rand;
 extern crate byteorder;
 extern crate tiny_keccak;
+#[cfg(feature = "serde")] extern crate serde;
 
 #[cfg(feature = "tor")] pub mod batcher;
+#[cfg(feature = "simulate")] pub mod simulate;
+#[cfg(feature = "internals")] #[doc(hidden)] pub mod internals;
+#[cfg(feature = "capi")] pub mod capi;
 mod reduce;
 mod ntt;
 mod error_correction;
 pub mod poly;
 pub mod params;
 pub mod newhope;
+pub mod message;
+pub mod pkcs8;
+pub mod error;
+pub mod workspace;
+pub mod frame;
+pub mod kdf;
+pub mod handshake;
+pub mod auth;
+pub mod aead;
+pub mod ticket;
+pub mod stream;
+pub mod envelope;
+pub mod lpr;
+pub mod pak;
 
 use rand::Rng;
 use tiny_keccak::Keccak;
@@ -18,52 +36,120 @@ pub use params::{
     N, Q,
     POLY_BYTES,
     SEEDBYTES, RECBYTES,
-    SENDABYTES, SENDBBYTES
+    SENDABYTES, SENDBBYTES,
+    Algorithm
 };
+pub use message::{ PublicKey, Offer, Reply };
+pub use error::Error;
+pub use workspace::Workspace;
+pub use handshake::{ Initiator, Responder };
 
 
-pub fn keygen<R: Rng>(mut r: R, sk: &mut [u8; POLY_BYTES], pk: &mut [u8; SENDABYTES]) {
-    let (mut ska, mut pka) = ([0; N], [0; N]);
+pub fn keygen<R: Rng>(r: R, sk: &mut [u8; POLY_BYTES], pk: &mut [u8; SENDABYTES]) {
+    keygen_with(&mut Workspace::new(), r, sk, pk)
+}
+
+pub fn sharedb<R: Rng>(r: R, sharedkey: &mut [u8; 32], pk: &[u8; SENDABYTES], msg: &mut [u8; SENDBBYTES]) {
+    sharedb_with(&mut Workspace::new(), r, sharedkey, pk, msg)
+}
+
+pub fn shareda(sharedkey: &mut [u8; 32], sk: &[u8; POLY_BYTES], msg: &[u8; SENDBBYTES]) {
+    shareda_with(&mut Workspace::new(), sharedkey, sk, msg)
+}
+
+
+fn uniform(ws: &mut Workspace, alg: Algorithm, nonce: &[u8]) {
+    match alg {
+        Algorithm::NewHope1024 => poly::uniform_standard(&mut ws.a, nonce),
+        #[cfg(feature = "tor")]
+        Algorithm::NewHope1024Tor => poly::uniform_tor_with(&mut ws.a, nonce, &mut ws.sort),
+        #[cfg(not(feature = "tor"))]
+        Algorithm::NewHope1024Tor => panic!("NewHope1024Tor needs the `tor` feature")
+    }
+}
+
+/// `keygen` using `ws` for scratch.
+pub fn keygen_with<R: Rng>(ws: &mut Workspace, r: R, sk: &mut [u8; POLY_BYTES], pk: &mut [u8; SENDABYTES]) {
+    keygen_alg(ws, Algorithm::CURRENT, r, sk, pk)
+}
+
+/// `sharedb` using `ws` for scratch.
+pub fn sharedb_with<R: Rng>(
+    ws: &mut Workspace, r: R,
+    sharedkey: &mut [u8; 32], pk: &[u8; SENDABYTES], msg: &mut [u8; SENDBBYTES]
+) {
+    sharedb_alg(ws, Algorithm::CURRENT, r, sharedkey, pk, msg)
+}
+
+/// `keygen_with` for any supported parameter set.
+pub(crate) fn keygen_alg<R: Rng>(
+    ws: &mut Workspace, alg: Algorithm, mut r: R,
+    sk: &mut [u8; POLY_BYTES], pk: &mut [u8; SENDABYTES]
+) {
     let (pk, nonce) = pk.split_at_mut(POLY_BYTES);
     r.fill_bytes(nonce);
+    uniform(ws, alg, nonce);
 
-    newhope::keygen(&mut ska, &mut pka, nonce, r);
+    // s
+    poly::noise(&mut ws.b, &mut r);
+    poly::ntt(&mut ws.b);
+    poly::poly_tobytes(&ws.b, sk);
 
-    poly::poly_tobytes(&ska, sk);
-    poly::poly_tobytes(&pka, pk);
+    // a * s + e
+    poly::pointwise_assign(&mut ws.a, &ws.b);
+    poly::noise(&mut ws.b, &mut r);
+    poly::ntt(&mut ws.b);
+    poly::add_assign(&mut ws.a, &ws.b);
+    poly::poly_tobytes(&ws.a, pk);
 }
 
-pub fn sharedb<R: Rng>(r: R, sharedkey: &mut [u8; 32], pk: &[u8; SENDABYTES], msg: &mut [u8; SENDBBYTES]) {
+/// `sharedb_with` for any supported parameter set.
+pub(crate) fn sharedb_alg<R: Rng>(
+    ws: &mut Workspace, alg: Algorithm, mut r: R,
+    sharedkey: &mut [u8; 32], pk: &[u8; SENDABYTES], msg: &mut [u8; SENDBBYTES]
+) {
     let (pk, nonce) = pk.split_at(POLY_BYTES);
     let (pkb_bytes, rec_bytes) = msg.split_at_mut(POLY_BYTES);
-    let mut pka = [0; N];
-    let mut pkb = [0; N];
-    let mut rec = [0; N];
-    poly::poly_frombytes(pk, &mut pka);
+    uniform(ws, alg, nonce);
 
-    newhope::sharedb(
-        sharedkey, &mut pkb, &mut rec,
-        &pka, nonce, r
-    );
+    // s'
+    poly::noise(&mut ws.b, &mut r);
+    poly::ntt(&mut ws.b);
+
+    // a * s' + e'
+    poly::pointwise_assign(&mut ws.a, &ws.b);
+    poly::noise(&mut ws.c, &mut r);
+    poly::ntt(&mut ws.c);
+    poly::add_assign(&mut ws.a, &ws.c);
+    poly::poly_tobytes(&ws.a, pkb_bytes);
+
+    // v = b * s' + e''
+    poly::noise(&mut ws.c, &mut r);
+    poly::poly_frombytes(pk, &mut ws.a);
+    poly::pointwise_assign(&mut ws.a, &ws.b);
+    poly::invntt(&mut ws.a);
+    poly::add_assign(&mut ws.a, &ws.c);
+
+    error_correction::helprec(&mut ws.b, &ws.a, &mut r);
+    error_correction::rec(sharedkey, &ws.a, &ws.b);
+    newhope::rec_tobytes(&ws.b, rec_bytes);
 
     let mut sha3 = Keccak::new_sha3_256();
     sha3.update(sharedkey);
     sha3.finalize(sharedkey);
-
-    poly::poly_tobytes(&pkb, pkb_bytes);
-    newhope::rec_tobytes(&rec, rec_bytes);
 }
 
-pub fn shareda(sharedkey: &mut [u8; 32], sk: &[u8; POLY_BYTES], msg: &[u8; SENDBBYTES]) {
-    let mut ska = [0; N];
-    let (mut pkb, mut rec) = ([0; N], [0; N]);
+/// `shareda` using `ws` for scratch.
+pub fn shareda_with(ws: &mut Workspace, sharedkey: &mut [u8; 32], sk: &[u8; POLY_BYTES], msg: &[u8; SENDBBYTES]) {
     let (pkb_bytes, rec_bytes) = msg.split_at(POLY_BYTES);
 
-    poly::poly_frombytes(sk, &mut ska);
-    poly::poly_frombytes(pkb_bytes, &mut pkb);
-    newhope::rec_frombytes(rec_bytes, &mut rec);
+    poly::poly_frombytes(sk, &mut ws.a);
+    poly::poly_frombytes(pkb_bytes, &mut ws.b);
+    poly::pointwise_assign(&mut ws.a, &ws.b);
+    poly::invntt(&mut ws.a);
 
-    newhope::shareda(sharedkey, &ska, &pkb, &rec);
+    newhope::rec_frombytes(rec_bytes, &mut ws.b);
+    error_correction::rec(sharedkey, &ws.a, &ws.b);
 
     let mut sha3 = Keccak::new_sha3_256();
     sha3.update(sharedkey);
@@ -0,0 +1,274 @@
+//! Lyubashevsky-Peikert-Regev public-key encryption over the NewHope ring.
+//!
+//! A research prototype, not a CCA-secure scheme: ciphertexts are malleable
+//! and decryption never fails loudly. With `a` uniform and `s, e, s', e', e''`
+//! drawn from `psi_16`, as `keygen` does:
+//!
+//! ```text
+//! public key: b = a*s + e, seed of a
+//! encrypt m:  u = a*s' + e',  v = b*s' + e'' + encode(m)
+//! decrypt:    decode(v - u*s) = decode(encode(m) + e*s' - e'*s + e'')
+//! ```
+//!
+//! `encode` spreads each of the 256 message bits over four coefficients,
+//! `i + 256 * j`, as 0 or `Q / 2`; `decode` adds up their distances from
+//! `Q / 2` and reads a 1 below `Q`. Each bit thus survives any error under
+//! `Q / 4` on all four coefficients, or an arbitrary error on one of them. The
+//! error term above has a standard deviation of about 370, more than eight
+//! times smaller than that margin, so honest decryptions don't fail in
+//! practice. Polynomials travel uncompressed.
+
+use rand::Rng;
+use ::params::{ N, Q, POLY_BYTES, SEEDBYTES };
+use ::poly::{ Poly, NttPoly };
+
+
+pub const MSG_BYTES: usize = 32;
+pub const PUBLIC_KEY_BYTES: usize = POLY_BYTES + SEEDBYTES;
+pub const SECRET_KEY_BYTES: usize = POLY_BYTES;
+pub const CIPHERTEXT_BYTES: usize = 2 * POLY_BYTES;
+
+/// Copies of each message bit.
+const SPREAD: usize = N / (8 * MSG_BYTES);
+const HALF_Q: u16 = Q as u16 / 2;
+
+
+/// Map each message bit to 0 or `Q / 2` on four coefficients.
+pub fn encode(msg: &[u8; MSG_BYTES]) -> Poly {
+    let mut r = Poly::zero();
+    for i in 0..8 * MSG_BYTES {
+        let bit = ((msg[i / 8] >> (i % 8)) & 1) as u16;
+        for j in 0..SPREAD {
+            r.coeffs[i + 8 * MSG_BYTES * j] = bit.wrapping_neg() & HALF_Q;
+        }
+    }
+    r
+}
+
+/// Recover the message bits from a noisy `encode`, without branching on
+/// the coefficients.
+pub fn decode(p: &Poly) -> [u8; MSG_BYTES] {
+    let c = p.reduced();
+    let mut msg = [0; MSG_BYTES];
+    for i in 0..8 * MSG_BYTES {
+        let t = (0..SPREAD).fold(0, |t, j| {
+            let x = c[i + 8 * MSG_BYTES * j] as i32 - HALF_Q as i32;
+            let mask = x >> 31;
+            t + ((x ^ mask) - mask)
+        });
+        let bit = ((t - Q as i32) >> 31) & 1;
+        msg[i / 8] |= (bit as u8) << (i % 8);
+    }
+    msg
+}
+
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PublicKey {
+    b: NttPoly,
+    seed: [u8; SEEDBYTES]
+}
+
+pub struct SecretKey {
+    s: NttPoly
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Ciphertext {
+    u: NttPoly,
+    v: Poly
+}
+
+impl PublicKey {
+    /// `b | seed`, the layout of a NewHope offer.
+    pub fn to_bytes(&self) -> [u8; PUBLIC_KEY_BYTES] {
+        let mut r = [0; PUBLIC_KEY_BYTES];
+        r[..POLY_BYTES].copy_from_slice(&self.b.to_bytes());
+        r[POLY_BYTES..].copy_from_slice(&self.seed);
+        r
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<PublicKey> {
+        if bytes.len() != PUBLIC_KEY_BYTES {
+            return None;
+        }
+        let mut seed = [0; SEEDBYTES];
+        seed.copy_from_slice(&bytes[POLY_BYTES..]);
+        Some(PublicKey { b: NttPoly::from_bytes(&bytes[..POLY_BYTES])?, seed })
+    }
+}
+
+impl SecretKey {
+    pub fn to_bytes(&self) -> [u8; SECRET_KEY_BYTES] {
+        self.s.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<SecretKey> {
+        Some(SecretKey { s: NttPoly::from_bytes(bytes)? })
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.s.coeffs = [0; N];
+    }
+}
+
+impl Ciphertext {
+    /// `u | v`.
+    pub fn to_bytes(&self) -> [u8; CIPHERTEXT_BYTES] {
+        let mut r = [0; CIPHERTEXT_BYTES];
+        r[..POLY_BYTES].copy_from_slice(&self.u.to_bytes());
+        r[POLY_BYTES..].copy_from_slice(&self.v.to_bytes());
+        r
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Ciphertext> {
+        if bytes.len() != CIPHERTEXT_BYTES {
+            return None;
+        }
+        Some(Ciphertext {
+            u: NttPoly::from_bytes(&bytes[..POLY_BYTES])?,
+            v: Poly::from_bytes(&bytes[POLY_BYTES..])?
+        })
+    }
+}
+
+
+pub fn keygen<R: Rng>(rng: &mut R) -> (PublicKey, SecretKey) {
+    let mut seed = [0; SEEDBYTES];
+    rng.fill_bytes(&mut seed);
+    let a = NttPoly::uniform(&seed);
+
+    let s = Poly::noise(rng).to_ntt();
+    let e = Poly::noise(rng).to_ntt();
+    let b = &(&a * &s) + &e;
+    (PublicKey { b, seed }, SecretKey { s })
+}
+
+pub fn encrypt<R: Rng>(rng: &mut R, pk: &PublicKey, msg: &[u8; MSG_BYTES]) -> Ciphertext {
+    let a = NttPoly::uniform(&pk.seed);
+    let s1 = Poly::noise(rng).to_ntt();
+    let e1 = Poly::noise(rng).to_ntt();
+    let e2 = Poly::noise(rng);
+
+    let u = &(&a * &s1) + &e1;
+    let v = &(&(&pk.b * &s1).from_ntt() + &e2) + &encode(msg);
+    Ciphertext { u, v }
+}
+
+/// Always returns a message; a wrong key or a damaged ciphertext yields
+/// garbage rather than an error.
+pub fn decrypt(sk: &SecretKey, ct: &Ciphertext) -> [u8; MSG_BYTES] {
+    decode(&(&ct.v - &(&ct.u * &sk.s).from_ntt()))
+}
+
+
+/// Centered representative of a coefficient.
+#[cfg(test)]
+fn centered(x: u16) -> i32 {
+    let x = x as i32;
+    if x > Q as i32 / 2 { x - Q as i32 } else { x }
+}
+
+#[test]
+fn test_encode_decode() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x6c70, 0x72]);
+    for _ in 0..16 {
+        let mut msg = [0; MSG_BYTES];
+        rng.fill_bytes(&mut msg);
+        let p = encode(&msg);
+        assert_eq!(decode(&p), msg);
+
+        // any error under Q / 4 on every coefficient
+        let mut noisy = p.clone();
+        for c in noisy.coeffs.iter_mut() {
+            let e = Q as u16 / 4 - 1;
+            *c = (*c + if rng.gen() { e } else { Q as u16 - e }) % Q as u16;
+        }
+        assert_eq!(decode(&noisy), msg);
+
+        // or anything at all on one copy of each bit
+        let mut noisy = p.clone();
+        for c in noisy.coeffs[..8 * MSG_BYTES].iter_mut() {
+            *c = rng.gen_range(0, Q as u16);
+        }
+        assert_eq!(decode(&noisy), msg);
+
+        // but shifting every copy by Q / 2 flips the bit
+        let mut flipped = p.clone();
+        for j in 0..SPREAD {
+            flipped.coeffs[5 + 8 * MSG_BYTES * j] += HALF_Q;
+        }
+        let mut expected = msg;
+        expected[0] ^= 1 << 5;
+        assert_eq!(decode(&flipped), expected);
+    }
+}
+
+#[test]
+fn test_lpr() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x6c70, 0x7265, 0x6e63]);
+    let (pk, sk) = keygen(&mut rng);
+    assert_eq!(PublicKey::from_bytes(&pk.to_bytes()), Some(pk.clone()));
+    assert_eq!(SecretKey::from_bytes(&sk.to_bytes()).unwrap().to_bytes()[..], sk.to_bytes()[..]);
+
+    let mut max_error = 0;
+    for _ in 0..64 {
+        let mut msg = [0; MSG_BYTES];
+        rng.fill_bytes(&mut msg);
+        let ct = encrypt(&mut rng, &pk, &msg);
+        assert_eq!(Ciphertext::from_bytes(&ct.to_bytes()), Some(ct.clone()));
+        assert_eq!(decrypt(&sk, &ct), msg);
+
+        // the error term stays far from the Q / 4 decoding margin
+        let noise = &(&ct.v - &(&ct.u * &sk.s).from_ntt()) - &encode(&msg);
+        max_error = noise.reduced().iter().map(|&c| centered(c).abs()).fold(max_error, ::std::cmp::max);
+    }
+    assert!(max_error > 0 && max_error < Q as i32 / 6, "max error {}", max_error);
+
+    assert_eq!(PublicKey::from_bytes(&[0xff; PUBLIC_KEY_BYTES]), None);
+    assert_eq!(Ciphertext::from_bytes(&[0; CIPHERTEXT_BYTES - 1]), None);
+}
+
+#[test]
+fn test_decryption_failure() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x6661, 0x696c]);
+    let (pk, sk) = keygen(&mut rng);
+    let msg = [0xa5; MSG_BYTES];
+    let ct = encrypt(&mut rng, &pk, &msg);
+    let distance = |a: &[u8; MSG_BYTES], b: &[u8; MSG_BYTES]| {
+        a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum::<u32>()
+    };
+
+    // the wrong key decrypts to noise
+    let (_, other) = keygen(&mut rng);
+    let garbage = decrypt(&other, &ct);
+    assert!(distance(&garbage, &msg) > 64 && distance(&garbage, &msg) < 192);
+
+    // v is malleable: adding Q / 2 to every copy of a bit flips it silently
+    let mut flipped = ct.clone();
+    for j in 0..SPREAD {
+        flipped.v.coeffs[8 * MSG_BYTES * j] += HALF_Q;
+    }
+    assert_eq!(distance(&decrypt(&sk, &flipped), &msg), 1);
+
+    // extra error on v flips more bits as it passes the Q / 4 margin
+    let failures = |bound: u16, rng: &mut ChaChaRng| {
+        let mut noisy = ct.clone();
+        for c in noisy.v.coeffs.iter_mut() {
+            let e = rng.gen_range(0, bound + 1);
+            *c = (*c + if rng.gen() { e } else { Q as u16 - e }) % Q as u16;
+        }
+        distance(&decrypt(&sk, &noisy), &msg)
+    };
+    assert_eq!(failures(Q as u16 / 8, &mut rng), 0);
+    let some = failures(Q as u16 / 2, &mut rng);
+    assert!(some > 0 && some < 128, "{} bits flipped", some);
+}
@@ -0,0 +1,253 @@
+use ::params::{ POLY_BYTES, SENDABYTES, SENDBBYTES };
+use ::poly::poly_check;
+
+
+/// Alice's public key, sent to Bob as the handshake offer.
+#[derive(Clone)]
+pub struct PublicKey(pub [u8; SENDABYTES]);
+
+/// The handshake offer is Alice's public key.
+pub type Offer = PublicKey;
+
+/// Bob's reply, carrying his public polynomial and the reconciliation data.
+#[derive(Clone)]
+pub struct Reply(pub [u8; SENDBBYTES]);
+
+impl PublicKey {
+    /// Parse an offer, rejecting it unless every coefficient is reduced mod `Q`.
+    pub fn from_bytes(bytes: &[u8]) -> Option<PublicKey> {
+        if bytes.len() != SENDABYTES || !poly_check(&bytes[..POLY_BYTES]) {
+            return None;
+        }
+
+        let mut pk = [0; SENDABYTES];
+        pk.copy_from_slice(bytes);
+        Some(PublicKey(pk))
+    }
+}
+
+impl Reply {
+    /// Parse a reply, rejecting it unless every coefficient is reduced mod `Q`.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Reply> {
+        if bytes.len() != SENDBBYTES || !poly_check(&bytes[..POLY_BYTES]) {
+            return None;
+        }
+
+        let mut msg = [0; SENDBBYTES];
+        msg.copy_from_slice(bytes);
+        Some(Reply(msg))
+    }
+}
+
+macro_rules! impl_bytes {
+    ( $name:ident ) => {
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &$name) -> bool {
+                self.0[..] == other.0[..]
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl ::std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                write!(f, concat!(stringify!($name), "({} bytes)"), self.0.len())
+            }
+        }
+    }
+}
+
+impl_bytes!(PublicKey);
+impl_bytes!(Reply);
+
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use std::fmt;
+    use serde::{ Serialize, Serializer, Deserialize, Deserializer };
+    use serde::de::{ self, Visitor, SeqAccess };
+    use super::{ PublicKey, Reply };
+
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    fn to_hex(bytes: &[u8]) -> String {
+        let mut s = String::with_capacity(bytes.len() * 2);
+        for &b in bytes {
+            s.push(HEX[(b >> 4) as usize] as char);
+            s.push(HEX[(b & 0xf) as usize] as char);
+        }
+        s
+    }
+
+    fn from_hex(s: &str) -> Option<Vec<u8>> {
+        fn nibble(c: u8) -> Option<u8> {
+            match c {
+                b'0'..=b'9' => Some(c - b'0'),
+                b'a'..=b'f' => Some(c - b'a' + 10),
+                b'A'..=b'F' => Some(c - b'A' + 10),
+                _ => None
+            }
+        }
+
+        let s = s.as_bytes();
+        if !s.len().is_multiple_of(2) {
+            return None;
+        }
+
+        s.chunks(2)
+            .map(|c| Some((nibble(c[0])? << 4) | nibble(c[1])?))
+            .collect()
+    }
+
+    macro_rules! impl_serde {
+        ( $name:ident, $len:expr ) => {
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    if serializer.is_human_readable() {
+                        serializer.serialize_str(&to_hex(&self.0))
+                    } else {
+                        serializer.serialize_bytes(&self.0)
+                    }
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$name, D::Error> {
+                    struct BytesVisitor;
+
+                    impl<'de> Visitor<'de> for BytesVisitor {
+                        type Value = $name;
+
+                        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                            write!(f, "{} bytes or a hex string of {} bytes", $len, $len)
+                        }
+
+                        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<$name, E> {
+                            if v.len() != $len {
+                                return Err(E::invalid_length(v.len(), &self));
+                            }
+                            $name::from_bytes(v)
+                                .ok_or_else(|| E::invalid_value(de::Unexpected::Bytes(v), &self))
+                        }
+
+                        fn visit_str<E: de::Error>(self, v: &str) -> Result<$name, E> {
+                            let bytes = from_hex(v)
+                                .ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))?;
+                            self.visit_bytes(&bytes)
+                        }
+
+                        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<$name, A::Error> {
+                            let mut bytes = Vec::with_capacity($len);
+                            while let Some(b) = seq.next_element()? {
+                                if bytes.len() == $len {
+                                    return Err(de::Error::invalid_length(bytes.len() + 1, &self));
+                                }
+                                bytes.push(b);
+                            }
+                            self.visit_bytes(&bytes)
+                        }
+                    }
+
+                    if deserializer.is_human_readable() {
+                        deserializer.deserialize_str(BytesVisitor)
+                    } else {
+                        deserializer.deserialize_bytes(BytesVisitor)
+                    }
+                }
+            }
+        }
+    }
+
+    impl_serde!(PublicKey, ::params::SENDABYTES);
+    impl_serde!(Reply, ::params::SENDBBYTES);
+}
+
+
+#[cfg(test)]
+fn handshake() -> (PublicKey, Reply) {
+    use rand::{ Rng, OsRng, ChaChaRng };
+
+    let mut rng = OsRng::new().unwrap().gen::<ChaChaRng>();
+    let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+    let (mut key, mut msg) = ([0; 32], [0; SENDBBYTES]);
+    ::keygen(&mut rng, &mut sk, &mut pk);
+    ::sharedb(&mut rng, &mut key, &pk, &mut msg);
+
+    (PublicKey(pk), Reply(msg))
+}
+
+#[test]
+fn test_from_bytes() {
+    let (pk, msg) = handshake();
+
+    assert_eq!(PublicKey::from_bytes(&pk.0), Some(pk.clone()));
+    assert_eq!(Reply::from_bytes(&msg.0), Some(msg.clone()));
+    assert_eq!(PublicKey::from_bytes(&pk.0[1..]), None);
+
+    let mut bad = pk.0;
+    bad[..7].copy_from_slice(&[0xff; 7]);
+    assert_eq!(PublicKey::from_bytes(&bad), None);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_json() {
+    extern crate serde_json;
+
+    let (pk, msg) = handshake();
+
+    let json = serde_json::to_string(&pk).unwrap();
+    assert_eq!(json.len(), 2 * SENDABYTES + 2);
+    assert_eq!(serde_json::from_str::<PublicKey>(&json).unwrap(), pk);
+
+    let json = serde_json::to_string(&msg).unwrap();
+    assert_eq!(serde_json::from_str::<Reply>(&json).unwrap(), msg);
+
+    let bad = format!("\"{}\"", "ff".repeat(SENDABYTES));
+    assert!(serde_json::from_str::<PublicKey>(&bad).is_err());
+    assert!(serde_json::from_str::<PublicKey>("\"00\"").is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_bincode() {
+    extern crate bincode;
+
+    let (pk, msg) = handshake();
+
+    let bytes = bincode::serialize(&pk).unwrap();
+    assert_eq!(bytes.len(), 8 + SENDABYTES);
+    assert_eq!(bincode::deserialize::<PublicKey>(&bytes).unwrap(), pk);
+
+    let bytes = bincode::serialize(&msg).unwrap();
+    assert_eq!(bincode::deserialize::<Reply>(&bytes).unwrap(), msg);
+
+    let mut bad = bincode::serialize(&pk).unwrap();
+    for b in &mut bad[8..15] {
+        *b = 0xff;
+    }
+    assert!(bincode::deserialize::<PublicKey>(&bad).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_seq() {
+    use std::iter;
+    use serde::Deserialize;
+    use serde::de::value::{ Error as ValueError, SeqDeserializer };
+
+    let (pk, _) = handshake();
+
+    let seq = SeqDeserializer::<_, ValueError>::new(pk.0.iter().cloned());
+    assert_eq!(PublicKey::deserialize(seq).unwrap(), pk);
+
+    // an endless sequence is cut off after one byte too many
+    let seq = SeqDeserializer::<_, ValueError>::new(iter::repeat(0u8));
+    assert!(PublicKey::deserialize(seq).is_err());
+}
@@ -118,3 +118,28 @@ pub fn sharedb<R: Rng>(
 pub fn shareda(sharedkey: &mut [u8], ska: &[u16], pkb: &[u16], c: &[u16]) {
     finish_computation(sharedkey, ska, pkb, c);
 }
+
+
+#[test]
+fn test_rec_frombytes_tobytes() {
+    use rand::{ SeedableRng, ChaChaRng };
+    use ::params::RECBYTES;
+
+    let mut rng = ChaChaRng::from_seed(&[0x6e68, 0x7265, 0x6362, 0x7974]);
+    let (mut c, mut c2) = ([0; N], [0; N]);
+    let (mut r, mut r2) = ([0; RECBYTES], [0; RECBYTES]);
+
+    for _ in 0..256 {
+        for x in c.iter_mut() {
+            *x = rng.gen_range(0, 4);
+        }
+        rec_tobytes(&c, &mut r);
+        rec_frombytes(&r, &mut c2);
+        assert_eq!(c[..], c2[..]);
+
+        rng.fill_bytes(&mut r);
+        rec_frombytes(&r, &mut c);
+        rec_tobytes(&c, &mut r2);
+        assert_eq!(r[..], r2[..]);
+    }
+}
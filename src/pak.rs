@@ -0,0 +1,441 @@
+//! Password-authenticated key exchange: RLWE-PAK.
+//!
+//! The protocol of Ding, Alsayigh, Lancrenon, RV and Snook ("Provably Secure
+//! Password Authenticated Key Exchange Based on RLWE for the Post-Quantum
+//! World", CT-RSA 2017), with NewHope's parameters and its four-dimensional
+//! reconciliation (`helprec`/`rec`) in place of the paper's `Cha`/`Mod2`.
+//! Both parties know the password and the two identities; there is no PKI.
+//!
+//! ```text
+//! gamma = H1(client id, server id, password)
+//!
+//! client -> server: m = a*s + e + gamma | seed
+//! server -> client: u = a*s' + e' | c = helprec(v) | k,
+//!                   where v = (m - gamma)*s' + e'' and sigma = rec(v, c)
+//! client -> server: k'
+//! ```
+//!
+//! The client recovers `sigma = rec(u*s, c)`. `k`, `k'` and the session key
+//! are H2, H3 and H4 of the identities, the first two flows without `k`,
+//! `sigma` and `gamma`; each side checks the other's tag before it releases
+//! the key, so a wrong password surfaces as `Error::KeyConfirmationFailed`.
+//!
+//! H1 squeezes four bytes per coefficient from cSHAKE256 and reduces them mod
+//! `Q`, so the work done never depends on the password; the bias is under
+//! `2^-18` per coefficient. H2 to H4 are cSHAKE256 under one label with a tag
+//! byte each. An eavesdropper learns nothing that tests a password guess, but
+//! an active attacker gets one guess per run, so rate-limit failures. Like
+//! `keygen`, `a` comes from the build's default sampler.
+
+use rand::Rng;
+use byteorder::{ ByteOrder, LittleEndian };
+use ::params::{ N, Q, POLY_BYTES, SEEDBYTES, RECBYTES, SENDABYTES, SENDBBYTES };
+use ::handshake::State;
+use ::kdf::{ CShake256, FUNCTION_NAME, ct_eq };
+use ::error::Error;
+use ::error_correction::{ helprec, rec };
+use ::newhope::{ rec_tobytes, rec_frombytes };
+use ::poly;
+
+
+const PASSWORD_LABEL: &[u8] = b"newhope pak password v1";
+const LABEL: &[u8] = b"newhope pak v1";
+
+const TAG_SERVER: u8 = 2;
+const TAG_CLIENT: u8 = 3;
+const TAG_KEY: u8 = 4;
+
+/// `m | seed`, the layout of a NewHope offer.
+pub const START_BYTES: usize = SENDABYTES;
+/// `u | c | k`.
+pub const RESPONSE_BYTES: usize = SENDBBYTES + 32;
+/// `k'`.
+pub const FINISH_BYTES: usize = 32;
+
+
+/// H1: the password as a uniform element of the ring, in the NTT domain.
+fn password_poly(client_id: &[u8], server_id: &[u8], password: &[u8], gamma: &mut [u16; N]) {
+    let mut cshake = CShake256::new(FUNCTION_NAME, PASSWORD_LABEL);
+    cshake.update_string(client_id);
+    cshake.update_string(server_id);
+    cshake.update_string(password);
+
+    let mut bytes = vec![0; 4 * N];
+    cshake.finalize(&mut bytes);
+    for (g, chunk) in gamma.iter_mut().zip(bytes.chunks(4)) {
+        *g = (LittleEndian::read_u32(chunk) % Q as u32) as u16;
+    }
+    bytes.fill(0);
+}
+
+/// What both parties know once `sigma` is agreed.
+struct Transcript<'a> {
+    client_id: &'a [u8],
+    server_id: &'a [u8],
+    start: &'a [u8],
+    response: &'a [u8],
+    sigma: &'a [u8; 32],
+    gamma: &'a [u16; N]
+}
+
+impl<'a> Transcript<'a> {
+    /// H2, H3 or H4, by `tag`.
+    fn hash(&self, tag: u8) -> [u8; 32] {
+        let mut gamma = [0; POLY_BYTES];
+        poly::poly_tobytes(self.gamma, &mut gamma);
+
+        let mut cshake = CShake256::new(FUNCTION_NAME, LABEL);
+        cshake.update_string(&[tag]);
+        cshake.update_string(self.client_id);
+        cshake.update_string(self.server_id);
+        cshake.update_string(self.start);
+        cshake.update_string(self.response);
+        cshake.update_string(self.sigma);
+        cshake.update_string(&gamma);
+        let mut out = [0; 32];
+        cshake.finalize(&mut out);
+        out
+    }
+}
+
+
+/// The party that knows the password and starts the exchange.
+pub struct Client {
+    state: State,
+    client_id: Vec<u8>,
+    server_id: Vec<u8>,
+    gamma: [u16; N],
+    sk: [u16; N],
+    start: Vec<u8>,
+    key: [u8; 32]
+}
+
+impl Client {
+    pub fn new(password: &[u8], client_id: &[u8], server_id: &[u8]) -> Client {
+        let mut gamma = [0; N];
+        password_poly(client_id, server_id, password, &mut gamma);
+        Client {
+            state: State::Start,
+            client_id: client_id.to_vec(),
+            server_id: server_id.to_vec(),
+            gamma,
+            sk: [0; N],
+            start: Vec::new(),
+            key: [0; 32]
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The first flow: a fresh key share masked with the password.
+    pub fn start<R: Rng>(&mut self, mut rng: R) -> Result<Vec<u8>, Error> {
+        if self.state != State::Start {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        let (mut a, mut e, mut m) = ([0; N], [0; N], [0; N]);
+        let mut seed = [0; SEEDBYTES];
+        rng.fill_bytes(&mut seed);
+        poly::uniform(&mut a, &seed);
+
+        poly::noise(&mut self.sk, &mut rng);
+        poly::ntt(&mut self.sk);
+        poly::noise(&mut e, &mut rng);
+        poly::ntt(&mut e);
+        poly::pointwise(&mut m, &self.sk, &a);
+        poly::add_assign(&mut m, &e);
+        poly::add_assign(&mut m, &self.gamma);
+
+        let mut start = vec![0; START_BYTES];
+        poly::poly_tobytes(&m, &mut start[..POLY_BYTES]);
+        start[POLY_BYTES..].copy_from_slice(&seed);
+        self.start = start.clone();
+        self.state = State::AwaitingReply;
+        Ok(start)
+    }
+
+    /// Check the server's response and return the last flow.
+    ///
+    /// A wrong password, on either side, fails here.
+    pub fn read_response(&mut self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.state != State::AwaitingReply {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        let result = self.finish(msg);
+        self.sk = [0; N];
+        self.state = if result.is_ok() { State::Complete } else { State::Failed };
+        result
+    }
+
+    fn finish(&mut self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        if msg.len() != RESPONSE_BYTES {
+            return Err(Error::InvalidLength);
+        }
+        if !poly::poly_check(&msg[..POLY_BYTES]) {
+            return Err(Error::InvalidKey);
+        }
+
+        let (mut u, mut c, mut v) = ([0; N], [0; N], [0; N]);
+        poly::poly_frombytes(&msg[..POLY_BYTES], &mut u);
+        rec_frombytes(&msg[POLY_BYTES..SENDBBYTES], &mut c);
+        poly::pointwise(&mut v, &self.sk, &u);
+        poly::invntt(&mut v);
+        let mut sigma = [0; 32];
+        rec(&mut sigma, &v, &c);
+
+        let transcript = Transcript {
+            client_id: &self.client_id,
+            server_id: &self.server_id,
+            start: &self.start,
+            response: &msg[..SENDBBYTES],
+            sigma: &sigma,
+            gamma: &self.gamma
+        };
+        if !ct_eq(&transcript.hash(TAG_SERVER), &msg[SENDBBYTES..]) {
+            return Err(Error::KeyConfirmationFailed);
+        }
+        self.key = transcript.hash(TAG_KEY);
+        Ok(transcript.hash(TAG_CLIENT).to_vec())
+    }
+
+    /// The session key, once the server has proven it knows the password.
+    pub fn key(&self) -> Option<&[u8; 32]> {
+        if self.state == State::Complete { Some(&self.key) } else { None }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.gamma = [0; N];
+        self.sk = [0; N];
+        self.key = [0; 32];
+    }
+}
+
+
+/// The party that checks the client's knowledge of the password.
+pub struct Server {
+    state: State,
+    client_id: Vec<u8>,
+    server_id: Vec<u8>,
+    gamma: [u16; N],
+    expected: [u8; 32],
+    key: [u8; 32]
+}
+
+impl Server {
+    pub fn new(password: &[u8], client_id: &[u8], server_id: &[u8]) -> Server {
+        let mut gamma = [0; N];
+        password_poly(client_id, server_id, password, &mut gamma);
+        Server {
+            state: State::Start,
+            client_id: client_id.to_vec(),
+            server_id: server_id.to_vec(),
+            gamma,
+            expected: [0; 32],
+            key: [0; 32]
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Unmask the client's share, reconcile and return the second flow.
+    pub fn read_start<R: Rng>(&mut self, mut rng: R, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.state != State::Start {
+            return Err(Error::UnexpectedMessage);
+        }
+        if msg.len() != START_BYTES || !poly::poly_check(&msg[..POLY_BYTES]) {
+            self.state = State::Failed;
+            return Err(if msg.len() != START_BYTES { Error::InvalidLength } else { Error::InvalidKey });
+        }
+
+        let (mut a, mut m, mut alpha) = ([0; N], [0; N], [0; N]);
+        poly::uniform(&mut a, &msg[POLY_BYTES..]);
+        poly::poly_frombytes(&msg[..POLY_BYTES], &mut m);
+        poly::sub(&mut alpha, &m, &self.gamma);
+
+        // u = a*s' + e', v = alpha*s' + e''
+        let (mut s1, mut e1, mut u, mut v) = ([0; N], [0; N], [0; N], [0; N]);
+        poly::noise(&mut s1, &mut rng);
+        poly::ntt(&mut s1);
+        poly::noise(&mut e1, &mut rng);
+        poly::ntt(&mut e1);
+        poly::pointwise(&mut u, &a, &s1);
+        poly::add_assign(&mut u, &e1);
+
+        poly::pointwise(&mut v, &alpha, &s1);
+        poly::invntt(&mut v);
+        poly::noise(&mut e1, &mut rng);
+        poly::add_assign(&mut v, &e1);
+        s1.fill(0);
+
+        let (mut c, mut sigma) = ([0; N], [0; 32]);
+        helprec(&mut c, &v, &mut rng);
+        rec(&mut sigma, &v, &c);
+
+        let mut response = vec![0; RESPONSE_BYTES];
+        poly::poly_tobytes(&u, &mut response[..POLY_BYTES]);
+        rec_tobytes(&c, &mut response[POLY_BYTES..POLY_BYTES + RECBYTES]);
+
+        let transcript = Transcript {
+            client_id: &self.client_id,
+            server_id: &self.server_id,
+            start: msg,
+            response: &response[..SENDBBYTES],
+            sigma: &sigma,
+            gamma: &self.gamma
+        };
+        let k = transcript.hash(TAG_SERVER);
+        self.expected = transcript.hash(TAG_CLIENT);
+        self.key = transcript.hash(TAG_KEY);
+        response[SENDBBYTES..].copy_from_slice(&k);
+
+        self.state = State::AwaitingConfirmation;
+        Ok(response)
+    }
+
+    /// Check the client's last flow and release the key.
+    pub fn read_finish(&mut self, msg: &[u8]) -> Result<(), Error> {
+        if self.state != State::AwaitingConfirmation {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        if ct_eq(&self.expected, msg) {
+            self.state = State::Complete;
+            Ok(())
+        } else {
+            self.state = State::Failed;
+            Err(Error::KeyConfirmationFailed)
+        }
+    }
+
+    /// The session key, once the client has proven it knows the password.
+    pub fn key(&self) -> Option<&[u8; 32]> {
+        if self.state == State::Complete { Some(&self.key) } else { None }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.gamma = [0; N];
+        self.key = [0; 32];
+    }
+}
+
+
+/// Run all three flows, each side with its own generator seeded from
+/// `seed`; the client's and the server's results.
+#[cfg(test)]
+fn exchange(seed: u32, client: &mut Client, server: &mut Server, tamper: Option<(usize, usize)>) -> (Result<(), Error>, Result<(), Error>) {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut client_rng = ChaChaRng::from_seed(&[0x636c, seed]);
+    let mut server_rng = ChaChaRng::from_seed(&[0x7376, seed]);
+    let mut flows = [client.start(&mut client_rng).unwrap(), Vec::new(), Vec::new()];
+    let flip = |flows: &mut [Vec<u8>; 3], flow| {
+        if let Some((f, i)) = tamper {
+            if f == flow {
+                flows[f][i] ^= 1;
+            }
+        }
+    };
+
+    flip(&mut flows, 0);
+    flows[1] = match server.read_start(&mut server_rng, &flows[0]) {
+        Ok(msg) => msg,
+        Err(err) => return (Err(Error::Incomplete), Err(err))
+    };
+    flip(&mut flows, 1);
+    flows[2] = match client.read_response(&flows[1]) {
+        Ok(msg) => msg,
+        Err(err) => return (Err(err), Err(Error::Incomplete))
+    };
+    flip(&mut flows, 2);
+    (Ok(()), server.read_finish(&flows[2]))
+}
+
+#[test]
+fn test_pak() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut client = Client::new(b"correct horse", b"alice", b"server.example");
+    let mut server = Server::new(b"correct horse", b"alice", b"server.example");
+    assert_eq!(server.read_finish(&[0; FINISH_BYTES]), Err(Error::UnexpectedMessage));
+    assert_eq!(exchange(1, &mut client, &mut server, None), (Ok(()), Ok(())));
+    assert_eq!(client.state(), State::Complete);
+    assert_eq!(server.state(), State::Complete);
+    assert_eq!(client.key(), server.key());
+    assert!(client.key().is_some());
+    assert_eq!(client.start(ChaChaRng::from_seed(&[0x636c])), Err(Error::UnexpectedMessage));
+
+    // fresh keys every run
+    let mut client2 = Client::new(b"correct horse", b"alice", b"server.example");
+    let mut server2 = Server::new(b"correct horse", b"alice", b"server.example");
+    assert_eq!(exchange(2, &mut client2, &mut server2, None), (Ok(()), Ok(())));
+    assert_ne!(client.key(), client2.key());
+
+    // flows are the NewHope offer and reply, plus the tags
+    let mut client = Client::new(b"pw", b"c", b"s");
+    let mut server = Server::new(b"pw", b"c", b"s");
+    let start = client.start(ChaChaRng::from_seed(&[0x7061, 0x6b])).unwrap();
+    assert_eq!(start.len(), START_BYTES);
+    let response = server.read_start(ChaChaRng::from_seed(&[0x7061, 0x6c]), &start).unwrap();
+    assert_eq!(response.len(), RESPONSE_BYTES);
+    assert_eq!(server.key(), None);
+    assert_eq!(client.read_response(&response).unwrap().len(), FINISH_BYTES);
+}
+
+#[test]
+fn test_pak_wrong_password() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    // the client catches a server with the wrong password, and vice versa
+    let passwords = [(&b"hunter2"[..], &b"hunter3"[..]), (b"", b"hunter2"), (b"hunter2", b"Hunter2")];
+    for (k, &(client_pw, server_pw)) in passwords.iter().enumerate() {
+        let mut client = Client::new(client_pw, b"alice", b"bob");
+        let mut server = Server::new(server_pw, b"alice", b"bob");
+        let (client_result, server_result) = exchange(10 + k as u32, &mut client, &mut server, None);
+        assert_eq!(client_result, Err(Error::KeyConfirmationFailed));
+        assert_eq!(server_result, Err(Error::Incomplete));
+        assert_eq!((client.key(), server.key()), (None, None));
+        assert_eq!(client.state(), State::Failed);
+    }
+
+    // passwords are bound to both identities
+    let mut client = Client::new(b"hunter2", b"alice", b"bob");
+    let mut server = Server::new(b"hunter2", b"mallory", b"bob");
+    assert_eq!(exchange(20, &mut client, &mut server, None).0, Err(Error::KeyConfirmationFailed));
+
+    // an impostor without the password can't finish as the client either
+    let mut server = Server::new(b"hunter2", b"alice", b"bob");
+    let mut impostor = Client::new(b"guess", b"alice", b"bob");
+    let start = impostor.start(ChaChaRng::from_seed(&[0x6d61, 0x6c6c])).unwrap();
+    server.read_start(ChaChaRng::from_seed(&[0x7376, 0x6d61]), &start).unwrap();
+    assert_eq!(server.read_finish(&[0; FINISH_BYTES]), Err(Error::KeyConfirmationFailed));
+    assert_eq!(server.key(), None);
+}
+
+#[test]
+fn test_pak_tamper() {
+    // m, u, c, k and k' are all covered
+    let flips = [(0, 0), (0, POLY_BYTES + 3), (1, 7), (1, POLY_BYTES + 1), (1, SENDBBYTES + 5), (2, 31)];
+    for (k, &(flow, i)) in flips.iter().enumerate() {
+        let mut client = Client::new(b"hunter2", b"alice", b"bob");
+        let mut server = Server::new(b"hunter2", b"alice", b"bob");
+        let (client_result, server_result) = exchange(30 + k as u32, &mut client, &mut server, Some((flow, i)));
+        assert!(client_result.is_err() || server_result.is_err(), "flow {} byte {}", flow, i);
+        assert_eq!(server.key(), None);
+    }
+
+    let mut password = [0; N];
+    password_poly(b"alice", b"bob", b"hunter2", &mut password);
+    assert!(password.iter().all(|&c| (c as usize) < Q));
+    let mut other = [0; N];
+    password_poly(b"alice", b"bob", b"hunter3", &mut other);
+    assert!(password[..] != other[..]);
+}
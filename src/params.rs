@@ -6,6 +6,52 @@ pub const RECBYTES: usize = 256;
 pub const SENDABYTES: usize = POLY_BYTES + SEEDBYTES;
 pub const SENDBBYTES: usize = POLY_BYTES + RECBYTES;
 
+/// Parameter set, including the sampler used to expand `a`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Reference sampler (`newhope-20160815/ref`).
+    NewHope1024,
+    /// Tor's constant-time batcher sampler (`torref`).
+    NewHope1024Tor
+}
+
+impl Algorithm {
+    /// The parameter set this crate was built with.
+    #[cfg(not(feature = "tor"))] pub const CURRENT: Algorithm = Algorithm::NewHope1024;
+    #[cfg(feature = "tor")] pub const CURRENT: Algorithm = Algorithm::NewHope1024Tor;
+
+    /// Every parameter set this build can run, `CURRENT` first.
+    #[cfg(not(feature = "tor"))] pub const SUPPORTED: &'static [Algorithm] = &[Algorithm::NewHope1024];
+    #[cfg(feature = "tor")] pub const SUPPORTED: &'static [Algorithm] = &[Algorithm::NewHope1024Tor, Algorithm::NewHope1024];
+
+    pub fn is_supported(&self) -> bool {
+        Algorithm::SUPPORTED.contains(self)
+    }
+
+    /// DER contents of the OID.
+    pub fn oid(&self) -> &'static [u8] {
+        match *self {
+            // 2.25.157427602422850831700158296482007193347
+            Algorithm::NewHope1024 => &[
+                0x69, 0x81, 0xec, 0xef, 0xba, 0x83, 0xe8, 0xbd, 0xc2, 0x84,
+                0xe5, 0xa3, 0xc1, 0xee, 0xe3, 0xaa, 0xe0, 0xae, 0xae, 0x03
+            ],
+            // 2.25.125142884794694399341185601492592643809
+            Algorithm::NewHope1024Tor => &[
+                0x69, 0x81, 0xbc, 0xa5, 0xd2, 0xa5, 0xc3, 0x90, 0xc2, 0xa5,
+                0x89, 0xb6, 0xbe, 0xa6, 0x94, 0x9f, 0xaa, 0xe1, 0x9d, 0x61
+            ]
+        }
+    }
+
+    pub(crate) fn from_oid(oid: &[u8]) -> Option<Algorithm> {
+        [Algorithm::NewHope1024, Algorithm::NewHope1024Tor].iter()
+            .find(|alg| alg.oid() == oid)
+            .cloned()
+    }
+}
+
+
 pub const OMEGAS_MONTGOMERY: [u16; N / 2] = [
     4075,  6974,  7373,  7965,  3262,  5079,  522,   2169,  6364,  1018,  1041,
     8775,  2344,  11011, 5574,  1973,  4536,  1050,  6844,  3860,  3818,  6118,
@@ -38,3 +38,41 @@ fn test_barrett_reduce() {
     assert_eq!(barrett_reduce(3456), 3456);
     assert_eq!(barrett_reduce(34567), 9989);
 }
+
+#[test]
+fn test_barrett_reduce_domain() {
+    for a in 0..(u16::MAX as u32 + 1) {
+        let r = barrett_reduce(a as u16) as u32;
+        assert!(r < 2 * Q as u32, "barrett_reduce({}) = {}", a, r);
+        assert_eq!(r % Q as u32, a % Q as u32);
+    }
+}
+
+#[test]
+fn test_montgomery_reduce_domain() {
+    use rand::{ Rng, SeedableRng, ChaChaRng };
+
+    // 2^-18 mod Q
+    const RINV: u64 = 576;
+    // largest input for which `a + u` does not overflow
+    const MAX: u32 = u32::MAX - ((1 << RLOG) - 1) * Q as u32;
+
+    let check = |a: u32| {
+        let r = montgomery_reduce(a) as u64;
+        assert!(r < 1 << 14, "montgomery_reduce({}) = {}", a, r);
+        assert_eq!(r % Q as u64, a as u64 * RINV % Q as u64, "montgomery_reduce({})", a);
+    };
+
+    for a in (0..1 << 16).chain(MAX - (1 << 16)..MAX + 1) {
+        check(a);
+    }
+
+    for a in (0..MAX).step_by(4093) {
+        check(a);
+    }
+
+    let mut rng = ChaChaRng::from_seed(&[0x6e68, 0x7265, 0x6475, 0x6365]);
+    for _ in 0..(1 << 16) {
+        check(rng.gen_range(0, MAX + 1));
+    }
+}
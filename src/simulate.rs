@@ -0,0 +1,219 @@
+//! Decryption-failure simulation for the reconciliation step.
+//!
+//! Runs the passive handshake with centered binomial noise `psi_k` and
+//! records whether `rec` agrees on both sides, together with the
+//! distribution of the per-coefficient difference `v - v'`. The real
+//! parameter set is `k = 16`; larger `k` make failures observable.
+
+use rand::Rng;
+use ::params::{ N, Q };
+use ::error_correction::{ helprec, rec };
+use ::poly::{ uniform, pointwise, add, ntt, invntt };
+
+/// Noise parameter of the real parameter set.
+pub const K: u32 = 16;
+
+
+#[derive(Clone)]
+pub struct Stats {
+    pub trials: u64,
+    /// Handshakes where the two keys differ.
+    pub failures: u64,
+    /// Key bits that differ, out of `256 * trials`.
+    pub bit_failures: u64,
+    /// Count of `v - v'` per value, centered: index `d + Q / 2` for `d` in `-Q/2..=Q/2`.
+    pub histogram: Vec<u64>
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats { trials: 0, failures: 0, bit_failures: 0, histogram: vec![0; Q] }
+    }
+
+    pub fn merge(&mut self, other: &Stats) {
+        self.trials += other.trials;
+        self.failures += other.failures;
+        self.bit_failures += other.bit_failures;
+        for (a, b) in self.histogram.iter_mut().zip(&other.histogram) {
+            *a += *b;
+        }
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        self.failures as f64 / self.trials as f64
+    }
+
+    pub fn bit_failure_rate(&self) -> f64 {
+        self.bit_failures as f64 / (256 * self.trials) as f64
+    }
+
+    /// Empirical variance of `v - v'`.
+    pub fn variance(&self) -> f64 {
+        let (count, sum) = self.histogram.iter()
+            .enumerate()
+            .fold((0., 0.), |(count, sum), (i, &c)| {
+                let d = i as f64 - (Q / 2) as f64;
+                (count + c as f64, sum + c as f64 * d * d)
+            });
+        sum / count
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        Stats::new()
+    }
+}
+
+/// Variance of `v - v' = e * s' + e'' - e' * s` for noise `psi_k`.
+pub fn variance(k: u32) -> f64 {
+    let var = k as f64 / 2.;
+    var + 2. * N as f64 * var * var
+}
+
+/// Fit `ln p = a + b / variance` through the observed points and evaluate
+/// it at `k`. Points are `(variance, bit failure rate)`; points without
+/// failures are ignored. Returns `None` unless two usable points remain.
+///
+/// The per-bit rate follows the Gaussian tail far better than the
+/// per-handshake rate, which saturates; a handshake fails with
+/// probability at most `256` times the result.
+pub fn extrapolate(points: &[(f64, f64)], k: u32) -> Option<f64> {
+    let points = points.iter()
+        .filter(|&&(_, p)| p > 0.)
+        .map(|&(var, p)| (1. / var, p.ln()))
+        .collect::<Vec<_>>();
+    if points.len() < 2 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mx = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let my = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let sxx = points.iter().map(|&(x, _)| (x - mx) * (x - mx)).sum::<f64>();
+    let sxy = points.iter().map(|&(x, y)| (x - mx) * (y - my)).sum::<f64>();
+    if sxx == 0. {
+        return None;
+    }
+
+    let b = sxy / sxx;
+    let a = my - b * mx;
+    Some((a + b / variance(k)).exp())
+}
+
+/// Sample from the centered binomial distribution `psi_k`, offset by `Q`.
+pub fn noise<R: Rng>(r: &mut [u16], k: u32, rng: &mut R) {
+    for x in r.iter_mut().take(N) {
+        let (mut a, mut b, mut left) = (0, 0, k);
+        while left > 0 {
+            let n = if left < 32 { left } else { 32 };
+            let mask = if n == 32 { !0 } else { (1u32 << n) - 1 };
+            let t = rng.next_u64();
+            a += (t as u32 & mask).count_ones();
+            b += ((t >> 32) as u32 & mask).count_ones();
+            left -= n;
+        }
+
+        *x = (a + Q as u32 - b) as u16;
+    }
+}
+
+/// Run `trials` handshakes with noise `psi_k`, accumulating into `stats`.
+pub fn run<R: Rng>(stats: &mut Stats, k: u32, trials: u64, rng: &mut R) {
+    let mut seed = [0; 32];
+    let (mut a, mut s, mut e, mut b) = ([0; N], [0; N], [0; N], [0; N]);
+    let (mut sp, mut ep, mut epp, mut u) = ([0; N], [0; N], [0; N], [0; N]);
+    let (mut v, mut vp, mut t, mut c) = ([0; N], [0; N], [0; N], [0; N]);
+
+    for _ in 0..trials {
+        rng.fill_bytes(&mut seed);
+        uniform(&mut a, &seed);
+
+        // Alice
+        noise(&mut s, k, rng);
+        ntt(&mut s);
+        noise(&mut e, k, rng);
+        ntt(&mut e);
+        pointwise(&mut t, &s, &a);
+        add(&mut b, &e, &t);
+
+        // Bob
+        noise(&mut sp, k, rng);
+        ntt(&mut sp);
+        noise(&mut ep, k, rng);
+        ntt(&mut ep);
+        noise(&mut epp, k, rng);
+        pointwise(&mut t, &a, &sp);
+        add(&mut u, &t, &ep);
+        pointwise(&mut t, &b, &sp);
+        invntt(&mut t);
+        add(&mut v, &t, &epp);
+
+        let mut keyb = [0; 32];
+        helprec(&mut c, &v, rng);
+        rec(&mut keyb, &v, &c);
+
+        // Alice
+        pointwise(&mut vp, &s, &u);
+        invntt(&mut vp);
+
+        let mut keya = [0; 32];
+        rec(&mut keya, &vp, &c);
+
+        for i in 0..N {
+            let d = (v[i] as usize + 2 * Q - (vp[i] as usize % Q)) % Q;
+            stats.histogram[(d + Q / 2) % Q] += 1;
+        }
+
+        let diff = keya.iter()
+            .zip(&keyb)
+            .map(|(x, y)| (x ^ y).count_ones() as u64)
+            .sum::<u64>();
+        stats.trials += 1;
+        stats.bit_failures += diff;
+        if diff != 0 {
+            stats.failures += 1;
+        }
+    }
+}
+
+
+#[test]
+fn test_noise() {
+    use rand::{ OsRng, ChaChaRng };
+
+    let mut rng = OsRng::new().unwrap().gen::<ChaChaRng>();
+    let mut r = [0; N];
+
+    for &k in &[1, 16, 32, 48] {
+        noise(&mut r, k, &mut rng);
+        assert!(r.iter().all(|&x| x as usize >= Q - k as usize && x as usize <= Q + k as usize));
+    }
+}
+
+#[test]
+fn test_run() {
+    use rand::{ OsRng, ChaChaRng };
+
+    let mut rng = OsRng::new().unwrap().gen::<ChaChaRng>();
+    let mut stats = Stats::new();
+    run(&mut stats, K, 8, &mut rng);
+
+    assert_eq!(stats.trials, 8);
+    assert_eq!(stats.failures, 0);
+    assert_eq!(stats.bit_failures, 0);
+    assert_eq!(stats.histogram.iter().sum::<u64>(), 8 * N as u64);
+
+    let ratio = stats.variance() / variance(K);
+    assert!(ratio > 0.8 && ratio < 1.2, "{}", ratio);
+}
+
+#[test]
+fn test_extrapolate() {
+    let f = |k: u32| (-5e6 / variance(k)).exp();
+    let points = [(variance(200), f(200)), (variance(300), f(300))];
+    let p = extrapolate(&points, K).unwrap();
+    assert!((p.ln() - f(K).ln()).abs() < 1e-6);
+
+    assert!(extrapolate(&points[..1], K).is_none());
+}
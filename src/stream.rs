@@ -0,0 +1,518 @@
+//! Encrypted byte streams.
+//!
+//! `SecureStream` runs a confirmed handshake over any `Read + Write` stream,
+//! then encrypts application data in records with `aead`. Every message on
+//! the wire is prefixed with its length as four big-endian bytes:
+//!
+//! ```text
+//! initiator -> responder: offer
+//! responder -> initiator: reply, confirmation
+//! initiator -> responder: confirmation
+//! either way:             record*, close
+//! ```
+//!
+//! A record is the sealed plaintext of at most `MAX_RECORD` bytes, with the
+//! length prefix as associated data. Each direction has its own key, exported
+//! from the session key, and counts its records from zero; the count and a
+//! last-record flag are the nonce, so dropped, replayed or reordered records
+//! fail to open. `close` sends the sealed empty record with the flag set;
+//! only that reads as end of file, and an underlying stream that ends without
+//! it is `UnexpectedEof`, so truncation is detected. Any failure breaks the
+//! stream for good.
+//!
+//! The handshake can carry a context, a pre-shared key or a resumption ticket
+//! through `connect_with` and `accept_with`; signatures are left to callers
+//! that drive `handshake` themselves.
+
+use std::io::{ self, Read, Write };
+use std::cmp;
+use rand::Rng;
+use byteorder::{ ByteOrder, BigEndian };
+use ::handshake::{ Initiator, Responder, State };
+use ::params::Algorithm;
+use ::error::Error;
+use ::{ aead, kdf };
+
+
+/// Largest plaintext in one record.
+pub const MAX_RECORD: usize = 16 * 1024;
+/// Largest handshake message accepted.
+const MAX_HANDSHAKE: usize = 64 * 1024;
+
+const INITIATOR_LABEL: &[u8] = b"newhope stream initiator";
+const RESPONDER_LABEL: &[u8] = b"newhope stream responder";
+
+fn invalid_data(err: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn write_message<W: Write>(inner: &mut W, msg: &[u8]) -> io::Result<()> {
+    let mut out = vec![0; 4];
+    BigEndian::write_u32(&mut out, msg.len() as u32);
+    out.extend_from_slice(msg);
+    inner.write_all(&out)?;
+    inner.flush()
+}
+
+fn read_message<R: Read>(inner: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    inner.read_exact(&mut len)?;
+    let len = BigEndian::read_u32(&len) as usize;
+    if len > MAX_HANDSHAKE {
+        return Err(invalid_data(Error::InvalidLength));
+    }
+    let mut msg = vec![0; len];
+    inner.read_exact(&mut msg)?;
+    Ok(msg)
+}
+
+/// One direction of the record layer.
+struct Direction {
+    key: [u8; aead::KEY_BYTES],
+    sequence: u64
+}
+
+impl Direction {
+    fn new(session_key: &[u8; 32], label: &[u8]) -> Direction {
+        let mut key = [0; aead::KEY_BYTES];
+        kdf::export(session_key, label, b"", &mut key);
+        Direction { key, sequence: 0 }
+    }
+
+    /// `sequence | last`.
+    fn nonce(&mut self, last: bool) -> io::Result<[u8; 9]> {
+        if self.sequence == u64::MAX {
+            return Err(io::Error::other("record sequence exhausted"));
+        }
+        let mut nonce = [0; 9];
+        BigEndian::write_u64(&mut nonce[..8], self.sequence);
+        nonce[8] = last as u8;
+        self.sequence += 1;
+        Ok(nonce)
+    }
+}
+
+impl Drop for Direction {
+    fn drop(&mut self) {
+        self.key = [0; aead::KEY_BYTES];
+    }
+}
+
+
+/// A stream whose bytes are encrypted and authenticated in both directions.
+pub struct SecureStream<T: Read + Write> {
+    inner: T,
+    algorithm: Algorithm,
+    exporter: [u8; 32],
+    send: Direction,
+    recv: Direction,
+    /// Decrypted bytes not yet read.
+    buf: Vec<u8>,
+    pos: usize,
+    /// The peer's close record has been opened.
+    eof: bool,
+    /// Our close record has been sent.
+    closed: bool,
+    broken: bool
+}
+
+impl<T: Read + Write> SecureStream<T> {
+    /// Run a handshake as the initiator with every supported parameter set.
+    pub fn connect<R: Rng>(inner: T, rng: R) -> io::Result<SecureStream<T>> {
+        SecureStream::connect_with(inner, rng, Initiator::new())
+    }
+
+    /// Run a handshake as the initiator; key confirmation is always on.
+    pub fn connect_with<R: Rng>(mut inner: T, rng: R, initiator: Initiator) -> io::Result<SecureStream<T>> {
+        let mut initiator = initiator.with_confirmation();
+        write_message(&mut inner, &initiator.offer(rng).map_err(invalid_data)?)?;
+
+        let reply = read_message(&mut inner)?;
+        initiator.read_reply(&reply).map_err(invalid_data)?;
+        if initiator.state() != State::AwaitingConfirmation {
+            return Err(invalid_data(Error::UnexpectedMessage));
+        }
+        let confirmation = read_message(&mut inner)?;
+        let own = initiator.confirmation().map_err(invalid_data)?;
+        initiator.read_confirmation(&confirmation).map_err(invalid_data)?;
+        write_message(&mut inner, &own)?;
+
+        let key = *initiator.key().ok_or_else(|| invalid_data(Error::Incomplete))?;
+        let algorithm = initiator.algorithm().ok_or_else(|| invalid_data(Error::Incomplete))?;
+        Ok(SecureStream::new(inner, algorithm, &key, INITIATOR_LABEL, RESPONDER_LABEL))
+    }
+
+    /// Run a handshake as the responder with every supported parameter set.
+    pub fn accept<R: Rng>(inner: T, rng: R) -> io::Result<SecureStream<T>> {
+        SecureStream::accept_with(inner, rng, Responder::new())
+    }
+
+    /// Run a handshake as the responder; key confirmation is always on.
+    pub fn accept_with<R: Rng>(mut inner: T, rng: R, responder: Responder) -> io::Result<SecureStream<T>> {
+        let mut responder = responder.with_confirmation();
+        let offer = read_message(&mut inner)?;
+        let reply = responder.read_offer(rng, &offer).map_err(invalid_data)?;
+        if responder.state() != State::AwaitingConfirmation {
+            return Err(invalid_data(Error::UnexpectedMessage));
+        }
+        write_message(&mut inner, &reply)?;
+        write_message(&mut inner, &responder.confirmation().map_err(invalid_data)?)?;
+
+        let confirmation = read_message(&mut inner)?;
+        responder.read_confirmation(&confirmation).map_err(invalid_data)?;
+
+        let key = *responder.key().ok_or_else(|| invalid_data(Error::Incomplete))?;
+        let algorithm = responder.algorithm().ok_or_else(|| invalid_data(Error::Incomplete))?;
+        Ok(SecureStream::new(inner, algorithm, &key, RESPONDER_LABEL, INITIATOR_LABEL))
+    }
+
+    fn new(inner: T, algorithm: Algorithm, key: &[u8; 32], send: &[u8], recv: &[u8]) -> SecureStream<T> {
+        let mut exporter = [0; 32];
+        kdf::export(key, b"newhope stream exporter", b"", &mut exporter);
+        SecureStream {
+            inner, algorithm, exporter,
+            send: Direction::new(key, send),
+            recv: Direction::new(key, recv),
+            buf: Vec::new(),
+            pos: 0,
+            eof: false,
+            closed: false,
+            broken: false
+        }
+    }
+
+    /// The negotiated parameter set.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Derive `len` bytes of key material for `label` and `context`,
+    /// independent of the record keys.
+    pub fn export(&self, label: &[u8], context: &[u8], len: usize) -> Vec<u8> {
+        let mut output = vec![0; len];
+        kdf::export(&self.exporter, label, context, &mut output);
+        output
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Reading or writing through this breaks the record layer.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Send the close record; the peer reads end of file after the data
+    /// written so far. Nothing more can be written.
+    pub fn close(&mut self) -> io::Result<()> {
+        self.check()?;
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        self.send_record(&[], true)?;
+        self.flush()
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if self.broken {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "secure stream failed"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Read and open the next record; `false` once the close record has
+    /// been opened.
+    fn read_record(&mut self) -> io::Result<bool> {
+        let mut header = [0; 4];
+        self.inner.read_exact(&mut header)?;
+
+        let len = BigEndian::read_u32(&header) as usize;
+        if !(aead::TAG_BYTES..=MAX_RECORD + aead::TAG_BYTES).contains(&len) {
+            return Err(invalid_data(Error::InvalidLength));
+        }
+        let mut sealed = vec![0; len];
+        self.inner.read_exact(&mut sealed)?;
+
+        // `write` never sends an empty data record
+        let last = len == aead::TAG_BYTES;
+        let nonce = self.recv.nonce(last)?;
+        self.buf = aead::open(&self.recv.key, &nonce, &header, &sealed).map_err(invalid_data)?;
+        self.pos = 0;
+        self.eof = last;
+        Ok(!last)
+    }
+
+    fn send_record(&mut self, data: &[u8], last: bool) -> io::Result<()> {
+        let mut record = vec![0; 4];
+        BigEndian::write_u32(&mut record, (data.len() + aead::TAG_BYTES) as u32);
+        let nonce = self.send.nonce(last)?;
+        let sealed = aead::seal(&self.send.key, &nonce, &record, data);
+        record.extend_from_slice(&sealed);
+
+        if let Err(err) = self.inner.write_all(&record) {
+            self.broken = true;
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Read + Write> Read for SecureStream<T> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        while self.pos == self.buf.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            match self.read_record() {
+                Ok(true) => (),
+                Ok(false) => return Ok(0),
+                Err(err) => {
+                    self.broken = true;
+                    return Err(err);
+                }
+            }
+        }
+
+        let n = cmp::min(out.len(), self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<T: Read + Write> Write for SecureStream<T> {
+    /// Send up to `MAX_RECORD` bytes of `data` as one record.
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.check()?;
+        if self.closed {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "secure stream closed"));
+        }
+        if data.is_empty() {
+            return Ok(0);
+        }
+
+        let n = cmp::min(data.len(), MAX_RECORD);
+        self.send_record(&data[..n], false)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+
+/// One end of an in-memory duplex pipe; every write is one message.
+#[cfg(test)]
+struct Pipe {
+    tx: ::std::sync::mpsc::Sender<Vec<u8>>,
+    rx: ::std::sync::mpsc::Receiver<Vec<u8>>,
+    buf: Vec<u8>
+}
+
+#[cfg(test)]
+impl Read for Pipe {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.buf.is_empty() {
+            match self.rx.recv() {
+                Ok(msg) => self.buf = msg,
+                Err(_) => return Ok(0)
+            }
+        }
+        let n = cmp::min(out.len(), self.buf.len());
+        out[..n].copy_from_slice(&self.buf[..n]);
+        self.buf.drain(..n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+impl Write for Pipe {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.tx.send(data.to_vec()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run a handshake over a fresh pipe, the responder on its own thread.
+#[cfg(test)]
+fn connected(initiator: Initiator, responder: Responder) -> (io::Result<SecureStream<Pipe>>, io::Result<SecureStream<Pipe>>) {
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let ((a_tx, b_rx), (b_tx, a_rx)) = (channel(), channel());
+    let a = Pipe { tx: a_tx, rx: a_rx, buf: Vec::new() };
+    let b = Pipe { tx: b_tx, rx: b_rx, buf: Vec::new() };
+    let server = thread::spawn(move || {
+        SecureStream::accept_with(b, ChaChaRng::from_seed(&[0x7365, 0x7276]), responder)
+    });
+    let client = SecureStream::connect_with(a, ChaChaRng::from_seed(&[0x636c, 0x6965]), initiator);
+    (client, server.join().unwrap())
+}
+
+#[test]
+fn test_stream_tcp() {
+    use std::net::{ TcpListener, TcpStream, Shutdown };
+    use std::thread;
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (socket, _) = listener.accept().unwrap();
+        let mut stream = SecureStream::accept(socket, ChaChaRng::from_seed(&[0x7463, 0x70])).unwrap();
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).unwrap();
+        data.reverse();
+        stream.write_all(&data).unwrap();
+        stream.close().unwrap();
+        stream.export(b"test", b"", 16)
+    });
+
+    let socket = TcpStream::connect(addr).unwrap();
+    let mut stream = SecureStream::connect(socket, ChaChaRng::from_seed(&[0x7463, 0x71])).unwrap();
+    let data = (0..3 * MAX_RECORD + 17).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    stream.write_all(&data).unwrap();
+    stream.close().unwrap();
+    stream.get_ref().shutdown(Shutdown::Write).unwrap();
+
+    let mut echoed = Vec::new();
+    stream.read_to_end(&mut echoed).unwrap();
+    echoed.reverse();
+    assert_eq!(echoed, data);
+    assert_eq!(stream.export(b"test", b"", 16), server.join().unwrap());
+}
+
+#[test]
+fn test_stream_records() {
+    let (client, server) = connected(Initiator::new().with_context(b"app"), Responder::new().with_context(b"app"));
+    let (mut client, mut server) = (client.unwrap(), server.unwrap());
+    assert_eq!(client.algorithm(), server.algorithm());
+
+    // per-direction keys: the same plaintext seals differently each way
+    client.write_all(b"ping").unwrap();
+    server.write_all(b"ping").unwrap();
+    let up = server.get_mut().rx.recv().unwrap();
+    let down = client.get_mut().rx.recv().unwrap();
+    assert_eq!(up.len(), 4 + 4 + aead::TAG_BYTES);
+    assert_ne!(up, down);
+    assert!(up.windows(4).all(|w| w != b"ping"));
+
+    server.get_mut().buf = up;
+    let mut buf = [0; 4];
+    server.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+
+    // reordered records fail, and the stream stays failed
+    client.write_all(b"one").unwrap();
+    client.write_all(b"two").unwrap();
+    let one = server.get_mut().rx.recv().unwrap();
+    let two = server.get_mut().rx.recv().unwrap();
+    server.get_mut().buf = [two, one].concat();
+    assert_eq!(server.read(&mut buf).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(server.read(&mut buf).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn test_stream_tamper() {
+    let (client, server) = connected(Initiator::new(), Responder::new());
+    let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+    // a flipped bit anywhere in a record, including its length
+    client.write_all(b"hello").unwrap();
+    let record = server.get_mut().rx.recv().unwrap();
+    drop(client);
+    for i in 0..record.len() {
+        let mut bad = record.clone();
+        bad[i] ^= 1;
+        server.get_mut().buf = bad;
+        server.recv.sequence = 0;
+        server.broken = false;
+        assert!(server.read(&mut [0; 5]).is_err());
+    }
+
+    // a truncated record, and a length no record can have
+    server.get_mut().buf = record[..record.len() - 1].to_vec();
+    server.recv.sequence = 0;
+    server.broken = false;
+    assert_eq!(server.read(&mut [0; 5]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    server.get_mut().buf = vec![0xff; 64];
+    server.broken = false;
+    assert_eq!(server.read(&mut [0; 5]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // the untouched record still opens
+    server.get_mut().buf = record;
+    server.recv.sequence = 0;
+    server.broken = false;
+    let mut buf = [0; 5];
+    server.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    assert_eq!(server.read(&mut buf).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_stream_close() {
+    let (client, server) = connected(Initiator::new(), Responder::new());
+    let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+    // end of file only after the close record
+    client.write_all(b"data").unwrap();
+    client.close().unwrap();
+    client.close().unwrap();
+    assert_eq!(client.write(b"more").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    let data = server.get_mut().rx.recv().unwrap();
+    let close = server.get_mut().rx.recv().unwrap();
+    assert_eq!(close.len(), 4 + aead::TAG_BYTES);
+    assert!(server.get_mut().rx.try_recv().is_err());
+
+    server.get_mut().buf = [&data[..], &close[..]].concat();
+    let mut out = Vec::new();
+    server.read_to_end(&mut out).unwrap();
+    assert_eq!(out, b"data");
+    assert_eq!(server.read(&mut [0; 4]).unwrap(), 0);
+
+    // the other direction is still open
+    server.write_all(b"reply").unwrap();
+    let mut buf = [0; 5];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"reply");
+
+    // dropping the close record, or forging one, is detected
+    let reset = |server: &mut SecureStream<Pipe>, buf: Vec<u8>| {
+        server.get_mut().buf = buf;
+        server.recv.sequence = 0;
+        server.eof = false;
+        server.broken = false;
+    };
+    drop(client);
+    reset(&mut server, data.clone());
+    assert_eq!(server.read_to_end(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    let mut forged = close.clone();
+    forged[10] ^= 1;
+    reset(&mut server, [&data[..], &forged[..]].concat());
+    assert_eq!(server.read_to_end(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // a close record out of place fails like any other record
+    reset(&mut server, close);
+    assert_eq!(server.read(&mut [0; 4]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_stream_handshake_failure() {
+    let (client, server) = connected(Initiator::new().with_context(b"a"), Responder::new().with_context(b"b"));
+    assert_eq!(client.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    assert!(server.is_err());
+}
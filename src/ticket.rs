@@ -0,0 +1,168 @@
+//! Session resumption tickets.
+//!
+//! A completed handshake gives both parties a resumption secret, derived from
+//! the session key and transcript by `kdf::resumption_secret`. The responder
+//! seals it, with the parameter set, issue time and lifetime, under its
+//! `TicketKey` (see `aead`) and hands the initiator the result as an opaque
+//! ticket, so it keeps no per-client state. To reconnect, the initiator
+//! presents the ticket with a fresh nonce, the responder opens it and answers
+//! with its own nonce, and both derive the new session key from the secret
+//! and both messages: no lattice exchange.
+//!
+//! A resumed session is only as forward-secret as the ticket key; rotate it
+//! at least every `MAX_LIFETIME`. A ticket can be presented more than once
+//! until it expires. Times are seconds since the Unix epoch.
+
+use rand::Rng;
+use byteorder::{ ByteOrder, BigEndian };
+use ::params::Algorithm;
+use ::error::Error;
+use ::{ aead, frame };
+
+
+/// Longest lifetime a ticket is issued with: seven days.
+pub const MAX_LIFETIME: u32 = 7 * 24 * 60 * 60;
+
+const LABEL: &[u8] = b"newhope ticket v1";
+const NONCE_BYTES: usize = 16;
+/// `issued (8) | lifetime (4) | sampler (1) | secret (32)`.
+const CONTENTS_BYTES: usize = 8 + 4 + 1 + 32;
+
+/// Responder-side key that seals tickets; it is zeroed on drop.
+#[derive(Clone)]
+pub struct TicketKey([u8; aead::KEY_BYTES]);
+
+impl TicketKey {
+    pub fn generate<R: Rng>(mut rng: R) -> TicketKey {
+        let mut key = [0; aead::KEY_BYTES];
+        rng.fill_bytes(&mut key);
+        TicketKey(key)
+    }
+
+    pub fn from_bytes(bytes: [u8; aead::KEY_BYTES]) -> TicketKey {
+        TicketKey(bytes)
+    }
+}
+
+impl Drop for TicketKey {
+    fn drop(&mut self) {
+        self.0 = [0; aead::KEY_BYTES];
+    }
+}
+
+
+/// What a ticket carries to the responder.
+pub(crate) struct Contents {
+    pub issued: u64,
+    pub lifetime: u32,
+    pub algorithm: Algorithm,
+    pub secret: [u8; 32]
+}
+
+impl Drop for Contents {
+    fn drop(&mut self) {
+        self.secret = [0; 32];
+    }
+}
+
+/// `nonce | sealed contents`.
+pub(crate) fn seal<R: Rng>(mut rng: R, key: &TicketKey, contents: &Contents) -> Vec<u8> {
+    let mut plaintext = [0; CONTENTS_BYTES];
+    BigEndian::write_u64(&mut plaintext[..8], contents.issued);
+    BigEndian::write_u32(&mut plaintext[8..12], contents.lifetime);
+    plaintext[12] = frame::sampler(contents.algorithm);
+    plaintext[13..].copy_from_slice(&contents.secret);
+
+    let mut ticket = vec![0; NONCE_BYTES];
+    rng.fill_bytes(&mut ticket);
+    let sealed = aead::seal(&key.0, &ticket, LABEL, &plaintext);
+    plaintext[13..].fill(0);
+
+    ticket.extend_from_slice(&sealed);
+    ticket
+}
+
+/// Open a ticket that is valid at `now`.
+pub(crate) fn open(key: &TicketKey, ticket: &[u8], now: u64) -> Result<Contents, Error> {
+    if ticket.len() != NONCE_BYTES + CONTENTS_BYTES + aead::TAG_BYTES {
+        return Err(Error::InvalidTicket);
+    }
+    let (nonce, sealed) = ticket.split_at(NONCE_BYTES);
+    let mut plaintext = aead::open(&key.0, nonce, LABEL, sealed)
+        .map_err(|_| Error::InvalidTicket)?;
+
+    let mut contents = Contents {
+        issued: BigEndian::read_u64(&plaintext[..8]),
+        lifetime: BigEndian::read_u32(&plaintext[8..12]),
+        algorithm: frame::from_sampler(plaintext[12]).ok_or(Error::InvalidTicket)?,
+        secret: [0; 32]
+    };
+    contents.secret.copy_from_slice(&plaintext[13..]);
+    plaintext.fill(0);
+
+    if now < contents.issued || now - contents.issued >= contents.lifetime as u64 {
+        return Err(Error::InvalidTicket);
+    }
+    Ok(contents)
+}
+
+
+/// A ticket as the initiator keeps it, with the resumption secret; the
+/// secret is zeroed on drop.
+#[derive(Clone)]
+pub struct Ticket {
+    pub(crate) opaque: Vec<u8>,
+    pub(crate) secret: [u8; 32],
+    pub(crate) algorithm: Algorithm,
+    pub(crate) expires: u64
+}
+
+impl Ticket {
+    /// Parameter set of the session that issued the ticket.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// When the responder stops accepting the ticket, by the initiator's
+    /// clock.
+    pub fn expires(&self) -> u64 {
+        self.expires
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.expires
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.secret = [0; 32];
+    }
+}
+
+
+#[test]
+fn test_ticket() {
+    use rand::{ SeedableRng, ChaChaRng };
+
+    let mut rng = ChaChaRng::from_seed(&[0x7469, 0x636b, 0x6574]);
+    let key = TicketKey::generate(&mut rng);
+    let contents = Contents { issued: 1000, lifetime: 60, algorithm: Algorithm::NewHope1024, secret: [3; 32] };
+    let ticket = seal(&mut rng, &key, &contents);
+    assert_eq!(ticket.len(), NONCE_BYTES + CONTENTS_BYTES + aead::TAG_BYTES);
+    assert!(ticket.windows(32).all(|w| w != [3; 32]));
+
+    let opened = open(&key, &ticket, 1000).unwrap();
+    assert_eq!((opened.issued, opened.lifetime, opened.secret), (1000, 60, [3; 32]));
+    assert_eq!(opened.algorithm, Algorithm::NewHope1024);
+    assert!(open(&key, &ticket, 1059).is_ok());
+
+    // expired, not yet valid, sealed under another key or tampered with
+    assert!(open(&key, &ticket, 1060).is_err());
+    assert!(open(&key, &ticket, 999).is_err());
+    assert!(open(&TicketKey::generate(&mut rng), &ticket, 1000).is_err());
+    let mut bad = ticket.clone();
+    bad[20] ^= 1;
+    assert!(open(&key, &bad, 1000).is_err());
+    assert!(open(&key, &ticket[1..], 1000).is_err());
+}
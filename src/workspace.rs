@@ -0,0 +1,145 @@
+//! Caller-provided scratch memory for `keygen_with`, `sharedb_with` and
+//! `shareda_with`.
+//!
+//! The handshake needs three polynomials of scratch, which is more than a
+//! small embedded stack can spare; a `Workspace` can instead live in a
+//! `static`, a `Box` or a long-lived task structure and be reused across
+//! handshakes. The plain `keygen`, `sharedb` and `shareda` put one on the
+//! stack.
+//!
+//! Besides the workspace, the `_with` functions keep only the Keccak state, a
+//! 168-byte SHAKE128 block, the 32-byte helper seed and the RNG passed by
+//! value on the stack. Debug builds need far more, about 90 KiB with `tor`,
+//! whose unrolled sorting network gets a large frame without optimisation.
+//! `test_stack_usage` runs a full handshake on a 16 KiB thread in release
+//! builds (64 KiB or 128 KiB in debug builds), which also holds the thread's
+//! own setup; that is the bound this crate checks.
+
+use ::params::N;
+#[cfg(feature = "tor")] use ::poly::UNIFORM_SCRATCH;
+
+
+/// Scratch for one handshake step: 6 KiB, plus 2.6 KiB with `tor`.
+///
+/// Holds secret intermediates after use; call `clear` before releasing
+/// memory that outlives the handshake.
+pub struct Workspace {
+    pub(crate) a: [u16; N],
+    pub(crate) b: [u16; N],
+    pub(crate) c: [u16; N],
+    #[cfg(feature = "tor")]
+    pub(crate) sort: [u16; UNIFORM_SCRATCH]
+}
+
+impl Workspace {
+    pub const fn new() -> Workspace {
+        Workspace {
+            a: [0; N],
+            b: [0; N],
+            c: [0; N],
+            #[cfg(feature = "tor")]
+            sort: [0; UNIFORM_SCRATCH]
+        }
+    }
+
+    /// Zero the scratch memory.
+    pub fn clear(&mut self) {
+        *self = Workspace::new();
+    }
+}
+
+impl Default for Workspace {
+    fn default() -> Workspace {
+        Workspace::new()
+    }
+}
+
+
+#[test]
+fn test_workspace() {
+    use rand::{ Rng, SeedableRng, ChaChaRng };
+    use tiny_keccak::Keccak;
+    use ::params::{ POLY_BYTES, SENDABYTES, SENDBBYTES };
+    use ::{ poly, newhope as nh };
+
+    fn sha3(key: &mut [u8; 32]) {
+        let mut sha3 = Keccak::new_sha3_256();
+        sha3.update(key);
+        sha3.finalize(key);
+    }
+
+    let mut rng = ChaChaRng::from_seed(&[0x776f, 0x726b, 0x7370, 0x6163]);
+    let mut ws = Workspace::new();
+
+    for _ in 0..16 {
+        let (seeda, seedb) = (rng.gen::<[u32; 4]>(), rng.gen::<[u32; 4]>());
+
+        // the coefficient-level functions with stack scratch
+        let mut ra = ChaChaRng::from_seed(&seeda);
+        let mut nonce = [0; 32];
+        ra.fill_bytes(&mut nonce);
+        let (mut ska, mut pka) = ([0; N], [0; N]);
+        nh::keygen(&mut ska, &mut pka, &nonce, ra);
+
+        let (mut pkb, mut c, mut keyb) = ([0; N], [0; N], [0; 32]);
+        nh::sharedb(&mut keyb, &mut pkb, &mut c, &pka, &nonce, ChaChaRng::from_seed(&seedb));
+        sha3(&mut keyb);
+
+        let mut keya = [0; 32];
+        nh::shareda(&mut keya, &ska, &pkb, &c);
+        sha3(&mut keya);
+
+        // the same handshake through the workspace
+        let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+        ::keygen_with(&mut ws, ChaChaRng::from_seed(&seeda), &mut sk, &mut pk);
+
+        let (mut sk2, mut pk2) = ([0; POLY_BYTES], [0; SENDABYTES]);
+        poly::poly_tobytes(&ska, &mut sk2);
+        poly::poly_tobytes(&pka, &mut pk2[..POLY_BYTES]);
+        pk2[POLY_BYTES..].copy_from_slice(&nonce);
+        assert_eq!(&sk[..], &sk2[..]);
+        assert_eq!(&pk[..], &pk2[..]);
+
+        let (mut key, mut msg) = ([0; 32], [0; SENDBBYTES]);
+        ::sharedb_with(&mut ws, ChaChaRng::from_seed(&seedb), &mut key, &pk, &mut msg);
+
+        let mut msg2 = [0; SENDBBYTES];
+        poly::poly_tobytes(&pkb, &mut msg2[..POLY_BYTES]);
+        nh::rec_tobytes(&c, &mut msg2[POLY_BYTES..]);
+        assert_eq!(&msg[..], &msg2[..]);
+        assert_eq!(key, keyb);
+
+        let mut key = [0; 32];
+        ::shareda_with(&mut ws, &mut key, &sk, &msg);
+        assert_eq!(key, keya);
+    }
+}
+
+#[test]
+fn test_stack_usage() {
+    use std::thread;
+    use rand::{ SeedableRng, ChaChaRng };
+    use ::params::{ POLY_BYTES, SENDABYTES, SENDBBYTES };
+
+    thread::Builder::new()
+        .stack_size(match (cfg!(debug_assertions), cfg!(feature = "tor")) {
+            (false, _) => 16,
+            (true, false) => 64,
+            (true, true) => 128
+        } * 1024)
+        .spawn(|| {
+            let mut ws = Box::new(Workspace::new());
+            let mut sk = Box::new([0; POLY_BYTES]);
+            let mut pk = Box::new([0; SENDABYTES]);
+            let mut msg = Box::new([0; SENDBBYTES]);
+            let (mut keya, mut keyb) = ([0; 32], [0; 32]);
+
+            ::keygen_with(&mut ws, ChaChaRng::from_seed(&[1]), &mut sk, &mut pk);
+            ::sharedb_with(&mut ws, ChaChaRng::from_seed(&[2]), &mut keyb, &pk, &mut msg);
+            ::shareda_with(&mut ws, &mut keya, &sk, &msg);
+            assert_eq!(keya, keyb);
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
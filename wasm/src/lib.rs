@@ -0,0 +1,150 @@
+//! WebAssembly bindings, see `README.md` for building.
+//!
+//! Messages and keys are `Uint8Array`s in the same encoding as the native
+//! implementation; malformed input throws.
+
+extern crate rand;
+extern crate wasm_bindgen;
+extern crate newhope;
+
+use rand::{ Rng, ChaChaRng };
+use wasm_bindgen::prelude::*;
+use newhope::{ POLY_BYTES, SENDABYTES, SENDBBYTES, PublicKey, Reply, Error };
+use newhope::poly;
+
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = crypto, js_name = getRandomValues)]
+    fn get_random_values(buf: &mut [u8]);
+}
+
+/// `crypto.getRandomValues`, available in browsers and Node >= 19.
+pub struct CryptoRng;
+
+impl Rng for CryptoRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0; 4];
+        self.fill_bytes(&mut buf);
+        u32::from(buf[0])
+            | u32::from(buf[1]) << 8
+            | u32::from(buf[2]) << 16
+            | u32::from(buf[3]) << 24
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        // getRandomValues rejects requests over 65536 bytes
+        for chunk in dest.chunks_mut(65536) {
+            get_random_values(chunk);
+        }
+    }
+}
+
+fn error(err: Error) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+
+#[wasm_bindgen]
+pub struct KeyPair {
+    sk: Vec<u8>,
+    pk: Vec<u8>
+}
+
+#[wasm_bindgen]
+impl KeyPair {
+    #[wasm_bindgen(getter, js_name = secretKey)]
+    pub fn secret_key(&self) -> Vec<u8> {
+        self.sk.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = publicKey)]
+    pub fn public_key(&self) -> Vec<u8> {
+        self.pk.clone()
+    }
+}
+
+#[wasm_bindgen]
+pub struct Accept {
+    key: Vec<u8>,
+    reply: Vec<u8>
+}
+
+#[wasm_bindgen]
+impl Accept {
+    #[wasm_bindgen(getter)]
+    pub fn key(&self) -> Vec<u8> {
+        self.key.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn reply(&self) -> Vec<u8> {
+        self.reply.clone()
+    }
+}
+
+
+/// Alice's first step: `{ secretKey, publicKey }`.
+#[wasm_bindgen]
+pub fn keygen() -> KeyPair {
+    let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+    newhope::keygen(CryptoRng.gen::<ChaChaRng>(), &mut sk, &mut pk);
+    KeyPair { sk: sk.to_vec(), pk: pk.to_vec() }
+}
+
+/// Bob's response to `publicKey`: `{ key, reply }`.
+#[wasm_bindgen]
+pub fn sharedb(pk: &[u8]) -> Result<Accept, JsValue> {
+    if pk.len() != SENDABYTES {
+        return Err(error(Error::InvalidLength));
+    }
+    let pk = PublicKey::from_bytes(pk).ok_or_else(|| error(Error::InvalidKey))?;
+
+    let (mut key, mut reply) = ([0; 32], [0; SENDBBYTES]);
+    newhope::sharedb(CryptoRng.gen::<ChaChaRng>(), &mut key, &pk.0, &mut reply);
+    Ok(Accept { key: key.to_vec(), reply: reply.to_vec() })
+}
+
+/// Alice's shared key from `secretKey` and Bob's `reply`.
+#[wasm_bindgen]
+pub fn shareda(sk: &[u8], reply: &[u8]) -> Result<Vec<u8>, JsValue> {
+    if sk.len() != POLY_BYTES || reply.len() != SENDBBYTES {
+        return Err(error(Error::InvalidLength));
+    }
+    if !poly::poly_check(sk) {
+        return Err(error(Error::InvalidKey));
+    }
+    let reply = Reply::from_bytes(reply).ok_or_else(|| error(Error::InvalidKey))?;
+    let mut secret = [0; POLY_BYTES];
+    secret.copy_from_slice(sk);
+
+    let mut key = [0; 32];
+    newhope::shareda(&mut key, &secret, &reply.0);
+    Ok(key.to_vec())
+}
+
+
+/// `tests/vectors.json` is produced by the native implementation.
+#[test]
+fn test_vectors() {
+    use rand::{ SeedableRng };
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    let mut rng = ChaChaRng::from_seed(&[0x6e68, 0x7761, 0x736d, 0x7465]);
+    let (mut sk, mut pk) = ([0; POLY_BYTES], [0; SENDABYTES]);
+    let (mut keya, mut keyb, mut reply) = ([0; 32], [0; 32], [0; SENDBBYTES]);
+
+    newhope::keygen(rng.gen::<ChaChaRng>(), &mut sk, &mut pk);
+    newhope::sharedb(rng.gen::<ChaChaRng>(), &mut keyb, &pk, &mut reply);
+    newhope::shareda(&mut keya, &sk, &reply);
+    assert_eq!(keya, keyb);
+
+    let vectors = format!(
+        "{{\n  \"secretKey\": \"{}\",\n  \"publicKey\": \"{}\",\n  \"reply\": \"{}\",\n  \"key\": \"{}\"\n}}\n",
+        hex(&sk), hex(&pk), hex(&reply), hex(&keya)
+    );
+    assert_eq!(vectors, include_str!("../tests/vectors.json"));
+}
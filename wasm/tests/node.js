@@ -0,0 +1,32 @@
+// Run after `wasm-pack build --target nodejs`.
+
+const assert = require('assert');
+const fs = require('fs');
+const path = require('path');
+const newhope = require('../pkg/newhope_wasm.js');
+
+const hex = s => Uint8Array.from(Buffer.from(s, 'hex'));
+const vectors = JSON.parse(fs.readFileSync(path.join(__dirname, 'vectors.json'), 'utf8'));
+const sk = hex(vectors.secretKey);
+const pk = hex(vectors.publicKey);
+
+// Alice against a reply from the native implementation
+assert.deepStrictEqual(newhope.shareda(sk, hex(vectors.reply)), hex(vectors.key));
+
+// Bob against a public key from the native implementation
+const accept = newhope.sharedb(pk);
+assert.strictEqual(accept.reply.length, hex(vectors.reply).length);
+assert.deepStrictEqual(newhope.shareda(sk, accept.reply), accept.key);
+
+// both sides in wasm
+const alice = newhope.keygen();
+const bob = newhope.sharedb(alice.publicKey);
+const key = newhope.shareda(alice.secretKey, bob.reply);
+assert.deepStrictEqual(key, bob.key);
+assert.notDeepStrictEqual(key, new Uint8Array(32));
+
+assert.throws(() => newhope.sharedb(pk.subarray(1)), /invalid length/);
+assert.throws(() => newhope.sharedb(new Uint8Array(pk.length).fill(0xff)), /invalid key/);
+assert.throws(() => newhope.shareda(new Uint8Array(sk.length).fill(0xff), accept.reply), /invalid key/);
+
+console.log('ok');